@@ -0,0 +1,89 @@
+//! Compares copying boards into fresh allocations with reusing the
+//! allocations of discarded boards through `clone_into`, as the solver does.
+//!
+//! States of a deal are expanded breadth-first, as many as the solver would
+//! expand, keeping a bounded queue; boards which do not fit are discarded.
+//!
+//! Usage: `clone_reuse [deal] [states]`; by default, the first deal from 1
+//! which the solver cannot settle within its budget, and 1000000 states.
+
+extern crate freecell;
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use freecell::freecell::{FreeCell, Variant};
+use freecell::solver::solve;
+
+const BUDGET: usize = 10_000;
+const QUEUE_LEN: usize = 10_000;
+
+/// Expands `states` states from `fc`, returning the number of cards
+/// left on the tableau across all of them.
+fn expand(fc: &FreeCell, states: usize, reuse: bool) -> usize {
+    let mut queue = VecDeque::new();
+    let mut pool = Vec::new();
+    let mut count = 0;
+    let mut cards = 0;
+
+    queue.push_back(fc.clone());
+
+    while let Some(fc) = queue.pop_front() {
+        for mv in fc.legal_moves() {
+            let mut next = match pool.pop() {
+                Some(mut b) if reuse => {
+                    fc.clone_into(&mut b);
+                    b
+                }
+                _ => fc.clone()
+            };
+
+            next.apply_move(mv).expect("illegal move");
+            cards += next.tableau_slots().iter().map(|t| t.len()).sum::<usize>();
+
+            count += 1;
+            if count == states {
+                return cards;
+            }
+
+            if queue.len() < QUEUE_LEN {
+                queue.push_back(next);
+            } else if reuse {
+                pool.push(next);
+            }
+        }
+
+        if reuse {
+            pool.push(fc);
+        }
+    }
+
+    cards
+}
+
+fn main() {
+    let args = std::env::args().skip(1)
+        .map(|s| s.parse().expect("expected a number"))
+        .collect::<Vec<usize>>();
+
+    let seed = args.first().map(|&n| n as u32).unwrap_or_else(|| {
+        (1..).find(|&n| solve(&FreeCell::with_seed(Variant::FreeCell, n), BUDGET)
+            .is_solvable().is_none()).unwrap()
+    });
+    let states = args.get(1).cloned().unwrap_or(1_000_000);
+
+    let fc = FreeCell::with_seed(Variant::FreeCell, seed);
+    let mut times = Vec::new();
+
+    for &(name, reuse) in &[("allocate", false), ("clone_into", true)] {
+        let start = Instant::now();
+        let cards = expand(&fc, states, reuse);
+        let secs = start.elapsed().as_secs_f64();
+
+        println!("deal {}, {}: {} states ({} cards) in {:.2}s",
+            seed, name, states, cards, secs);
+        times.push(secs);
+    }
+
+    println!("clone_into speedup: {:.2}x", times[0] / times[1]);
+}
@@ -0,0 +1,46 @@
+//! Solves a range of deals, first on one thread and then on several,
+//! reporting the win rate and the time taken by each, and the speedup
+//! of the threaded run over the sequential one.
+//!
+//! Usage: `solve_range [first] [last] [threads]`; by default, deals 1 to 1000
+//! on 4 threads.
+
+extern crate freecell;
+
+use std::time::Instant;
+
+use freecell::freecell::Variant;
+use freecell::solver::{solve_range, RangeSummary, Solution};
+
+const BUDGET: usize = 10_000;
+
+fn report(threads: usize, results: &[(u32, Solution)], secs: f64) {
+    let sum = RangeSummary::new(results);
+    let rate = sum.win_rate().map_or("-".to_owned(), |r| format!("{:.1}%", r));
+
+    println!("{} thread(s): {} of {} solved, {} unknown, win rate {}, in {:.2}s",
+        threads, sum.solved, results.len(), sum.unknown, rate, secs);
+}
+
+fn main() {
+    let args = std::env::args().skip(1)
+        .map(|s| s.parse().expect("expected a number"))
+        .collect::<Vec<u32>>();
+
+    let first = args.first().cloned().unwrap_or(1);
+    let last = args.get(1).cloned().unwrap_or(1000);
+    let threads = args.get(2).cloned().unwrap_or(4) as usize;
+
+    let mut times = Vec::new();
+
+    for &n in &[1, threads] {
+        let start = Instant::now();
+        let results = solve_range(Variant::FreeCell, first..=last, BUDGET, n);
+        let secs = start.elapsed().as_secs_f64();
+
+        report(n, &results, secs);
+        times.push(secs);
+    }
+
+    println!("speedup on {} threads: {:.2}x", threads, times[0] / times[1]);
+}
@@ -0,0 +1,59 @@
+//! Solves a deal, writes the solution in notation with the hash of each
+//! resulting board, then reads it back and verifies that it wins the game.
+
+extern crate freecell;
+
+use freecell::freecell::{FreeCell, Variant};
+use freecell::notation::{format_step, parse_step};
+use freecell::solver::{solve, Solution};
+use freecell::{replay_hashes, verify_replay};
+
+fn main() {
+    let seed = std::env::args().nth(1)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(1);
+    let variant = Variant::FreeCell;
+
+    let moves = match solve(&FreeCell::with_seed(variant, seed), 100_000) {
+        Solution::Solved(moves) => moves,
+        other => {
+            println!("deal {}: no solution found ({:?})", seed, other);
+            return;
+        }
+    };
+
+    let hashes = replay_hashes(variant, seed, &moves).expect("illegal solution");
+
+    // Write each move as it would be made on the board
+    let mut fc = FreeCell::with_seed(variant, seed);
+    let mut text = Vec::new();
+
+    while fc.sweep_step(u32::max_value()) {}
+
+    for (&mv, &hash) in moves.iter().zip(&hashes) {
+        text.push(format_step(&fc, mv, hash));
+        fc.apply_move(mv).expect("illegal move");
+        while fc.sweep_step(u32::max_value()) {}
+    }
+
+    println!("deal {}: {}", seed, text.join(" "));
+
+    // Read the solution back, replaying it to resolve each move
+    let mut fc = FreeCell::with_seed(variant, seed);
+    let mut parsed = Vec::new();
+
+    while fc.sweep_step(u32::max_value()) {}
+
+    for s in &text {
+        let step = parse_step(&fc, s).expect("invalid move notation");
+        fc.apply_move(step.0).expect("illegal move");
+        while fc.sweep_step(u32::max_value()) {}
+        parsed.push(step);
+    }
+
+    match verify_replay(variant, seed, &parsed) {
+        Ok(true) => println!("verified"),
+        Ok(false) => println!("moves do not win the game"),
+        Err(e) => println!("invalid solution: {}", e),
+    }
+}
@@ -2,14 +2,18 @@ use std::cmp::min;
 use std::fmt;
 
 use mortal::Color as TermColor;
-use rand::{thread_rng, seq::SliceRandom};
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
 
 pub const ACE: u8 = 1;
 pub const JACK: u8 = 11;
 pub const QUEEN: u8 = 12;
 pub const KING: u8 = 13;
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+/// A playing card
+///
+/// Cards are ordered by suit, in `SUITS` order, then by value.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize)]
 pub struct Card {
     pub suit: Suit,
     pub value: Face,
@@ -23,33 +27,124 @@ impl Card {
         }
     }
 
+    /// Parses a card code: a rank, as accepted by `Face::from_code`,
+    /// followed by a suit letter, one of `CDHS`; e.g. `"7H"` or `"10S"`.
+    pub fn from_code(s: &str) -> Option<Card> {
+        let ch = s.chars().last()?;
+        let rank = &s[..s.len() - ch.len_utf8()];
+
+        Some(Card::new(Suit::from_letter(ch)?, Face::from_code(rank)?))
+    }
+
+    /// Returns the card code, such as `"7H"` or `"TS"`.
+    pub fn code(&self) -> String {
+        format!("{}{}", self.value.code(), self.suit.letter())
+    }
+
+    /// Returns the card of the same suit and the next higher value,
+    /// or `None` for a king.
+    pub fn one_higher(&self) -> Option<Card> {
+        self.value.next().map(|v| Card::new(self.suit, v))
+    }
+
+    /// Returns the card of the same suit and the next lower value,
+    /// or `None` for an ace.
+    pub fn one_lower(&self) -> Option<Card> {
+        self.value.prev().map(|v| Card::new(self.suit, v))
+    }
+
     /// Returns whether `self` is the same suit as and a lower value than the
     /// given card.
     ///
     /// This is most useful for checking whether `self` has been moved to
     /// foundation: `self.is_lower(foundation) == self is on foundation`.
     pub fn is_lower(&self, other: Card) -> bool {
-        self.suit == other.suit && self.value.0 < other.value.0
+        self.suit == other.suit && self.value < other.value
     }
 
     /// Returns whether `self` may be placed atop `other` on the tableau.
     pub fn can_top(&self, other: Card) -> bool {
-        self.value.0 == other.value.0 - 1 && self.suit.color() != other.suit.color()
+        self.value.next() == Some(other.value) && self.suit.color() != other.suit.color()
     }
 
     /// Returns whether `self` may succeed the given card; or an empty slot
     /// if the given card is `None`.
     pub fn can_succeed(&self, other: Option<Card>) -> bool {
         match other {
-            Some(c) => self.value.0 == c.value.0 + 1,
-            None => self.value.0 == ACE
+            Some(c) => c.one_higher() == Some(*self),
+            None => self.value.is_ace()
         }
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize)]
 pub struct Face(pub u8);
 
+impl Face {
+    /// Returns each face, from ace to king.
+    pub fn all() -> impl Iterator<Item = Face> {
+        FACES.iter().map(|&v| Face(v))
+    }
+
+    /// Returns whether the face is an ace.
+    pub fn is_ace(&self) -> bool {
+        self.0 == ACE
+    }
+
+    /// Returns whether the face is a king.
+    pub fn is_king(&self) -> bool {
+        self.0 == KING
+    }
+
+    /// Returns the next higher face, or `None` for a king.
+    pub fn next(&self) -> Option<Face> {
+        if self.0 < KING {
+            Some(Face(self.0 + 1))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the next lower face, or `None` for an ace.
+    pub fn prev(&self) -> Option<Face> {
+        if self.0 > ACE {
+            Some(Face(self.0 - 1))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the rank as written in card codes; `T` stands for 10.
+    pub fn code(&self) -> char {
+        match self.0 {
+            ACE => 'A',
+            10 => 'T',
+            JACK => 'J',
+            QUEEN => 'Q',
+            KING => 'K',
+            n => (b'0' + n) as char
+        }
+    }
+
+    /// Parses a rank, one of `A23456789TJQK` in either case, or `10`.
+    pub fn from_code(s: &str) -> Option<Face> {
+        let n = match &*s.to_ascii_uppercase() {
+            "A" => ACE,
+            "T" | "10" => 10,
+            "J" => JACK,
+            "Q" => QUEEN,
+            "K" => KING,
+            s if s.len() == 1 => match s.as_bytes()[0] {
+                b @ b'2' ..= b'9' => b - b'0',
+                _ => return None
+            },
+            _ => return None
+        };
+
+        Some(Face(n))
+    }
+}
+
 impl fmt::Display for Face {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.0 {
@@ -62,7 +157,8 @@ impl fmt::Display for Face {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+/// Suit of a card, ordered as in `SUITS`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize)]
 pub enum Suit {
     Club,
     Diamond,
@@ -96,6 +192,11 @@ pub const FOUNDATION_SLOTS: usize = NUM_SUITS;
 pub const TABLEAU_SLOTS: usize = 8;
 
 impl Suit {
+    /// Returns each suit, in `SUITS` order.
+    pub fn all() -> impl Iterator<Item = Suit> {
+        SUITS.iter().cloned()
+    }
+
     pub fn as_index(&self) -> usize {
         match *self {
             Suit::Club => 0,
@@ -120,255 +221,1513 @@ impl Suit {
             Suit::Spade => '\u{2660}',
         }
     }
-}
 
-/// Returns a new shuffled deck.
-fn new_deck() -> Vec<Card> {
-    let mut deck = Vec::with_capacity(52);
+    /// Returns the letter identifying the suit in card codes.
+    pub fn letter(&self) -> char {
+        match *self {
+            Suit::Club => 'C',
+            Suit::Diamond => 'D',
+            Suit::Heart => 'H',
+            Suit::Spade => 'S',
+        }
+    }
 
-    for &suit in &SUITS {
-        for &value in &FACES {
-            deck.push(Card::new(suit, Face(value)));
+    /// Returns the suit identified by the given letter, in either case.
+    pub fn from_letter(ch: char) -> Option<Suit> {
+        match ch.to_ascii_uppercase() {
+            'C' => Some(Suit::Club),
+            'D' => Some(Suit::Diamond),
+            'H' => Some(Suit::Heart),
+            'S' => Some(Suit::Spade),
+            _ => None
         }
     }
+}
 
-    deck.shuffle(&mut thread_rng());
+/// Rules of a FreeCell-type game
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Variant {
+    /// Standard FreeCell: four reserve slots; build down by alternate colors
+    FreeCell,
+    /// As FreeCell, but build down by suit
+    BakersGame,
+    /// Eight reserve slots, four of which are dealt cards;
+    /// build down by suit; only kings may fill an empty tableau slot
+    EightOff,
+    /// As FreeCell, but with a deck of ace through eight of each suit
+    QuickFreeCell,
+}
 
-    deck
+impl Default for Variant {
+    fn default() -> Variant { Variant::FreeCell }
 }
 
-fn fill_tableau(deck: Vec<Card>) -> Vec<Vec<Card>> {
-    let mut tbl = vec![Vec::new(); TABLEAU_SLOTS];
+/// All game variants
+pub const VARIANTS: [Variant; 4] = [
+    Variant::FreeCell, Variant::BakersGame, Variant::EightOff, Variant::QuickFreeCell,
+];
 
-    for (i, card) in deck.into_iter().enumerate() {
-        tbl[i % TABLEAU_SLOTS].push(card);
+impl Variant {
+    /// Returns the display name of the variant.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Variant::FreeCell => "FreeCell",
+            Variant::BakersGame => "Baker's Game",
+            Variant::EightOff => "Eight Off",
+            Variant::QuickFreeCell => "Quick FreeCell",
+        }
     }
 
-    tbl
-}
+    /// Returns a short identifier for the variant.
+    pub fn key(&self) -> &'static str {
+        match *self {
+            Variant::FreeCell => "free_cell",
+            Variant::BakersGame => "bakers_game",
+            Variant::EightOff => "eight_off",
+            Variant::QuickFreeCell => "quick_free_cell",
+        }
+    }
 
-#[derive(Clone, Debug)]
-pub struct FreeCell {
-    reserve: [Option<Card>; RESERVE_SLOTS],
-    foundation: [Option<Card>; FOUNDATION_SLOTS],
-    tableau: Vec<Vec<Card>>,
-}
+    /// Returns the variant with the given identifier, as given by `key`.
+    pub fn from_key(key: &str) -> Option<Variant> {
+        VARIANTS.iter().cloned().find(|v| v.key() == key)
+    }
 
-impl FreeCell {
-    pub fn new() -> FreeCell {
-        FreeCell{
-            reserve: [None; RESERVE_SLOTS],
-            foundation: [None; FOUNDATION_SLOTS],
-            tableau: fill_tableau(new_deck()),
+    /// Returns the variant following this one in `VARIANTS`, wrapping around.
+    pub fn next(&self) -> Variant {
+        let pos = VARIANTS.iter().position(|v| v == self).unwrap();
+        VARIANTS[(pos + 1) % VARIANTS.len()]
+    }
+
+    /// Returns the number of reserve slots.
+    pub fn reserve_slots(&self) -> usize {
+        match *self {
+            Variant::FreeCell | Variant::BakersGame | Variant::QuickFreeCell => RESERVE_SLOTS,
+            Variant::EightOff => 8,
         }
     }
 
-    pub fn can_move_to_tableau(&self, card: Card, pos: usize) -> bool {
-        let slot = &self.tableau[pos];
+    /// Returns the number of tableau slots.
+    pub fn tableau_slots(&self) -> usize {
+        TABLEAU_SLOTS
+    }
 
-        slot.last().map_or(true, |&top| card.can_top(top))
+    /// Returns the number of cards dealt to the reserve.
+    pub fn reserve_deal(&self) -> usize {
+        match *self {
+            Variant::FreeCell | Variant::BakersGame | Variant::QuickFreeCell => 0,
+            Variant::EightOff => 4,
+        }
     }
 
-    pub fn can_move_to_foundation(&self, card: Card) -> bool {
-        let slot = self.foundation(card.suit);
+    /// Returns whether tableau cards are built down by suit,
+    /// rather than by alternate colors.
+    pub fn build_by_suit(&self) -> bool {
+        match *self {
+            Variant::FreeCell | Variant::QuickFreeCell => false,
+            Variant::BakersGame | Variant::EightOff => true,
+        }
+    }
 
-        card.can_succeed(slot)
+    /// Returns whether only kings may be placed in an empty tableau slot.
+    ///
+    /// In a deck without kings, this applies to its highest rank.
+    pub fn kings_only(&self) -> bool {
+        *self == Variant::EightOff
     }
 
-    pub fn should_move_to_foundation(&self, card: Card) -> bool {
-        if !self.can_move_to_foundation(card) {
-            return false;
+    /// Returns the cards in play.
+    pub fn deck(&self) -> Deck {
+        match *self {
+            Variant::FreeCell | Variant::BakersGame | Variant::EightOff => STANDARD_DECK,
+            Variant::QuickFreeCell => Deck{suits: &SUITS, max_rank: 8},
         }
+    }
+}
 
-        let club_v =    self.foundation(Suit::Club)   .map_or(0, |c| c.value.0);
-        let space_v =   self.foundation(Suit::Spade)  .map_or(0, |c| c.value.0);
-        let diamond_v = self.foundation(Suit::Diamond).map_or(0, |c| c.value.0);
-        let heart_v =   self.foundation(Suit::Heart)  .map_or(0, |c| c.value.0);
+/// Cards in play in a game: each card of the given suits
+/// from ace up to the highest rank
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Deck {
+    /// Suits in play, in `SUITS` order
+    pub suits: &'static [Suit],
+    /// Highest rank in play
+    pub max_rank: u8,
+}
 
-        let min_black = min(club_v, space_v);
-        let min_red = min(diamond_v, heart_v);
+/// The standard deck of 52 cards
+pub const STANDARD_DECK: Deck = Deck{suits: &SUITS, max_rank: KING};
 
-        if card.suit.color() == Color::Black {
-            card.value.0 <= min(min_black + 3, min_red + 2)
-        } else {
-            card.value.0 <= min(min_red + 3, min_black + 2)
-        }
+impl Deck {
+    /// Returns each card of the deck, by suit and then by rank.
+    pub fn cards(&self) -> impl Iterator<Item = Card> {
+        let max_rank = self.max_rank;
+
+        self.suits.iter().flat_map(move |&suit| {
+            Face::all().take_while(move |v| v.0 <= max_rank)
+                .map(move |v| Card::new(suit, v))
+        })
     }
 
-    /// Returns whether any reserve slots are vacant.
-    pub fn reserve_free(&self) -> bool {
-        self.reserve.iter().any(|r| r.is_none())
+    /// Returns the number of cards in the deck.
+    pub fn len(&self) -> usize {
+        self.suits.len() * self.max_rank as usize
     }
 
-    pub fn game_over(&self) -> bool {
-        self.foundation.iter().all(
-            |f| f.map_or(false, |c| c.value.0 == KING))
+    /// Returns whether the deck holds no cards.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 
-    pub fn add_to_foundation(&mut self, card: Card) {
-        self.assert_free(card);
-        assert!(self.can_move_to_foundation(card));
+    /// Returns whether the deck includes the given suit.
+    pub fn has_suit(&self, suit: Suit) -> bool {
+        self.suits.contains(&suit)
+    }
 
-        let slot = self.foundation_mut(card.suit);
-        *slot = Some(card);
+    /// Returns whether the deck includes the given card.
+    pub fn contains(&self, card: Card) -> bool {
+        self.has_suit(card.suit) && card.value.0 >= ACE && card.value.0 <= self.max_rank
     }
+}
 
-    pub fn add_to_tableau(&mut self, card: Card, pos: usize) {
-        self.assert_free(card);
-        assert!(self.can_move_to_tableau(card, pos));
+/// A move of one or more cards between slots
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Move {
+    /// Move a card from a reserve slot to foundation
+    ReserveToFoundation(usize),
+    /// Move a card from a reserve slot to a tableau slot
+    ReserveToTableau(usize, usize),
+    /// Move the top card of a tableau slot to foundation
+    TableauToFoundation(usize),
+    /// Move the top card of a tableau slot to reserve
+    TableauToReserve(usize),
+    /// Move a number of cards from one tableau slot to another
+    TableauToTableau(usize, usize, usize),
+}
 
-        self.tableau[pos].push(card);
+impl Move {
+    /// Returns whether the move places a card on foundation.
+    pub fn is_to_foundation(&self) -> bool {
+        match *self {
+            Move::ReserveToFoundation(_) | Move::TableauToFoundation(_) => true,
+            _ => false
+        }
     }
 
-    pub fn move_tableau_group(&mut self, a: usize, b: usize, n: usize) {
-        assert!(n != 0);
-        assert!(a != b);
-        assert!(n <= self.move_capacity(a, b));
+    /// Returns whether the move takes a card from reserve.
+    pub fn is_from_reserve(&self) -> bool {
+        match *self {
+            Move::ReserveToFoundation(_) | Move::ReserveToTableau(..) => true,
+            _ => false
+        }
+    }
 
-        let (a, b) = two_mut_refs(&mut self.tableau, a, b);
+    /// Returns whether the move carries cards between tableau slots.
+    pub fn is_tableau_to_tableau(&self) -> bool {
+        match *self {
+            Move::TableauToTableau(..) => true,
+            _ => false
+        }
+    }
 
-        let start = a.len() - n;
-        b.extend(a.drain(start..));
+    /// Returns the number of cards moved.
+    pub fn card_count(&self) -> usize {
+        match *self {
+            Move::TableauToTableau(_, _, n) => n,
+            _ => 1
+        }
     }
+}
 
-    pub fn add_to_reserve(&mut self, card: Card) {
-        self.assert_free(card);
+/// Location of a card in a game
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Position {
+    /// In the given reserve slot
+    Reserve(usize),
+    /// On foundation
+    Foundation,
+    /// In the given tableau slot, at the given depth from the bottom
+    Tableau(usize, usize),
+}
+
+/// Notable change to a game, found by `FreeCell::events_since`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    /// The given tableau slot became empty
+    ColumnEmptied(usize),
+    /// The ace of the given suit reached foundation
+    AceHome(Suit),
+    /// Every card of the given suit reached foundation
+    SuitComplete(Suit),
+    /// The last free reserve slot was filled
+    ReserveFilled,
+}
 
-        match self.reserve.iter_mut().find(|r| r.is_none()) {
-            Some(r) => *r = Some(card),
-            None => panic!("reserve is full")
+/// Reason a move cannot be made
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MoveError {
+    /// The source slot holds no card
+    EmptySource,
+    /// No reserve slot is vacant
+    ReserveFull,
+    /// Too few vacant slots to move a group of cards
+    NotEnoughSpace,
+    /// The card cannot be placed at the destination
+    InvalidDestination,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            MoveError::EmptySource => "Slot is empty",
+            MoveError::ReserveFull => "No free reserve slots",
+            MoveError::NotEnoughSpace => "Not enough free slots to move",
+            MoveError::InvalidDestination => "Cannot move cards",
+        })
+    }
+}
+
+/// Inconsistency in the cards of a game
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IntegrityError {
+    /// A card has a value outside the range of ace to king
+    InvalidCard(Card),
+    /// A foundation slot holds a card of another suit
+    WrongSuit(Suit, Card),
+    /// A card appears more than once
+    Duplicate(Card),
+    /// A card appears nowhere
+    Missing(Card),
+    /// The number of reserve or tableau slots does not match the variant
+    SlotCount,
+}
+
+impl fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IntegrityError::InvalidCard(c) =>
+                write!(f, "Invalid card value {} of suit {}", c.value.0, c.suit.char()),
+            IntegrityError::WrongSuit(suit, c) =>
+                write!(f, "Foundation for {} holds {}{}", suit.char(), c.suit.char(), c.value),
+            IntegrityError::Duplicate(c) =>
+                write!(f, "Duplicate card {}{}", c.suit.char(), c.value),
+            IntegrityError::Missing(c) =>
+                write!(f, "Missing card {}{}", c.suit.char(), c.value),
+            IntegrityError::SlotCount => f.write_str("Wrong number of slots for variant"),
         }
     }
+}
 
-    /// Automatically moves to foundation up to `n` cards.
-    /// Returns whether any cards were moved.
-    pub fn sweep_step(&mut self, n: u32) -> bool {
-        let mut left = n;
+/// Reason a board could not be read from a compact string or fc-solve layout
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ParseBoardError {
+    /// The string is not well-formed
+    Syntax,
+    /// The variant is not known
+    UnknownVariant,
+    /// The number of reserve or tableau slots does not match the variant
+    WrongSlotCount,
+    /// The cards do not form a complete deck
+    Integrity(IntegrityError),
+}
 
-        for (n, r) in self.reserve.clone().iter().cloned().enumerate() {
-            if let Some(c) = r {
-                if self.should_move_to_foundation(c) {
-                    self.remove_reserve(n);
-                    self.add_to_foundation(c);
+impl fmt::Display for ParseBoardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseBoardError::Syntax => f.write_str("Invalid board string"),
+            ParseBoardError::UnknownVariant => f.write_str("Unknown variant"),
+            ParseBoardError::WrongSlotCount =>
+                f.write_str("Wrong number of slots for variant"),
+            ParseBoardError::Integrity(e) => fmt::Display::fmt(&e, f),
+        }
+    }
+}
 
-                    left -= 1;
-                    if left == 0 {
-                        break;
-                    }
-                }
-            }
+/// Reason a game could not be dealt from a `DealConfig`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DealConfigError {
+    /// More reserve cards are given than the variant has reserve slots
+    TooManyReserve,
+    /// Too few cards remain to place one in each tableau slot
+    TooFewCards,
+    /// The cards given are not legal in place
+    Integrity(IntegrityError),
+}
+
+impl fmt::Display for DealConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DealConfigError::TooManyReserve => f.write_str("Too many reserve cards"),
+            DealConfigError::TooFewCards => f.write_str("Too few cards left to deal"),
+            DealConfigError::Integrity(e) => fmt::Display::fmt(&e, f),
         }
+    }
+}
 
-        if left != 0 {
-            let sweep = self.tableau.iter().cloned().enumerate()
-                .filter_map(|(i, t)| t.last().map(|&c| (i, c)))
-                .filter(|&(_, c)| self.should_move_to_foundation(c))
-                .map(|(i, _)| i).collect::<Vec<_>>();
+/// Cards placed before a deal, for handicaps and puzzles
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DealConfig {
+    /// Cards placed in the reserve, from the first slot
+    pub reserve: Vec<Card>,
+    /// Top card of each foundation slot, in suit order
+    pub foundation: [Option<Card>; FOUNDATION_SLOTS],
+}
 
-            for i in sweep.into_iter().take(left as usize) {
-                let c = self.pop_tableau(i);
-                self.add_to_foundation(c);
+impl DealConfig {
+    /// Returns a configuration with each suit of the deck
+    /// on foundation from ace up to the given rank.
+    pub fn home_to(deck: Deck, rank: Face) -> DealConfig {
+        let mut foundation = [None; FOUNDATION_SLOTS];
 
-                left -= 1;
+        for (f, &suit) in foundation.iter_mut().zip(&SUITS) {
+            if deck.has_suit(suit) {
+                *f = Some(Card::new(suit, Face(min(rank.0, deck.max_rank))));
             }
         }
 
-        left != n
+        DealConfig{
+            reserve: Vec::new(),
+            foundation: foundation,
+        }
     }
+}
 
-    pub fn remove_reserve(&mut self, pos: usize) -> Card {
-        self.reserve[pos].take().expect("reserve is empty")
-    }
+/// Written in a compact string in place of an empty slot
+const EMPTY_CODE: &str = "--";
 
-    pub fn reserve_slots(&self) -> &[Option<Card>] { &self.reserve }
+/// Offset basis of the 64-bit FNV-1a hash, used by `canonical_hash`
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+/// Prime of the 64-bit FNV-1a hash
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
 
-    pub fn reserve(&self, pos: usize) -> Option<Card> {
-        self.reserve[pos]
+/// Returns the remainder of `line` following `label`, ignoring ASCII case.
+fn strip_label<'a>(line: &'a str, label: &str) -> Option<&'a str> {
+    match line.get(..label.len()) {
+        Some(head) if head.eq_ignore_ascii_case(label) => Some(&line[label.len()..]),
+        _ => None
     }
+}
 
-    pub fn tableau_slots(&self) -> &[Vec<Card>] { &self.tableau }
-
-    pub fn tableau(&self, pos: usize) -> &[Card] {
-        &self.tableau[pos]
+/// Returns the cards of a deck, shuffled by the given seed.
+///
+/// Cards are shuffled as in Microsoft FreeCell, so that a seed deals the same
+/// game in every version; for the standard deck, it is the Microsoft deal
+/// of that number.
+fn new_deck(deck: Deck, seed: u32) -> Vec<Card> {
+    // Ordered by rank, then suit
+    let mut cards = deck.cards().collect::<Vec<_>>();
+    cards.sort_by_key(|c| (c.value, c.suit));
+
+    let mut rng = DealRng(seed);
+    let mut deck = Vec::with_capacity(cards.len());
+
+    while !cards.is_empty() {
+        let i = rng.next() as usize % cards.len();
+        deck.push(cards.swap_remove(i));
     }
 
-    pub fn tableau_mut(&mut self, pos: usize) -> &mut Vec<Card> {
-        &mut self.tableau[pos]
-    }
+    deck
+}
 
-    pub fn pop_tableau(&mut self, pos: usize) -> Card {
-        self.tableau[pos].pop().expect("tableau is empty")
-    }
+/// Linear congruential generator of the Microsoft C runtime's `rand`
+struct DealRng(u32);
 
-    fn assert_free(&self, card: Card) {
-        assert!(self.reserve.iter().all(|&r| r != Some(card)),
-            "card is not free; found in reserve");
-        assert!(!self.foundation.iter().any(
-            |r| r.map_or(false, |r| card.is_lower(r))),
-            "card is not free; found in foundation");
-        assert!(self.tableau.iter().all(|t| !t.contains(&card)),
-            "card is not free; found in tableau");
+impl DealRng {
+    fn next(&mut self) -> u32 {
+        self.0 = self.0.wrapping_mul(214_013).wrapping_add(2_531_011);
+        (self.0 >> 16) & 0x7fff
     }
+}
 
-    pub fn foundation_slots(&self) -> &[Option<Card>] { &self.foundation }
+fn fill_tableau(deck: Vec<Card>, n: usize) -> Vec<Vec<Card>> {
+    let mut tbl = vec![Vec::new(); n];
 
-    pub fn foundation(&self, suit: Suit) -> Option<Card> {
-        self.foundation[suit.as_index()]
+    for (i, card) in deck.into_iter().enumerate() {
+        tbl[i % n].push(card);
     }
 
-    fn foundation_mut(&mut self, suit: Suit) -> &mut Option<Card> {
-        &mut self.foundation[suit.as_index()]
+    tbl
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FreeCell {
+    #[serde(default)]
+    variant: Variant,
+    /// Reserve cards stay in the slot where they were placed until removed;
+    /// slots are never compacted or reordered.
+    reserve: Vec<Option<Card>>,
+    foundation: [Option<Card>; FOUNDATION_SLOTS],
+    tableau: Vec<Vec<Card>>,
+}
+
+impl FreeCell {
+    pub fn new() -> FreeCell {
+        FreeCell::with_variant(Variant::FreeCell)
     }
 
-    pub fn group_size(&self, pos: usize) -> usize {
-        let slot = &self.tableau[pos];
+    /// Deals a new game of the given variant.
+    pub fn with_variant(variant: Variant) -> FreeCell {
+        FreeCell::with_seed(variant, thread_rng().gen())
+    }
 
-        if slot.is_empty() {
-            return 0;
-        }
-        if slot.len() == 1 {
-            return 1;
-        }
+    /// Deals the game of the given variant identified by `seed`.
+    ///
+    /// The same seed always deals the same game.
+    pub fn with_seed(variant: Variant, seed: u32) -> FreeCell {
+        let mut deck = new_deck(variant.deck(), seed);
 
-        let mut n = 1;
+        debug_assert!(deck.len() >= variant.reserve_deal() + variant.tableau_slots(),
+            "deck does not fill each tableau slot");
 
-        let pairs = slot.iter().zip(slot[1..].iter());
+        let mut reserve = vec![None; variant.reserve_slots()];
 
-        for (&a, &b) in pairs.rev() {
-            if b.can_top(a) {
-                n += 1;
-            } else {
-                break;
-            }
+        for (r, card) in reserve.iter_mut()
+                .zip(deck.drain(..variant.reserve_deal())) {
+            *r = Some(card);
         }
 
-        n
+        FreeCell{
+            variant: variant,
+            reserve: reserve,
+            foundation: [None; FOUNDATION_SLOTS],
+            tableau: fill_tableau(deck, variant.tableau_slots()),
+        }
     }
 
-    pub fn move_capacity(&self, a: usize, b: usize) -> usize {
-        assert!(a != b);
+    /// Deals the game of the given variant identified by `seed`,
+    /// with the cards of `config` placed first.
+    ///
+    /// The cards given are removed from the shuffled deck, which is then dealt
+    /// as usual; cards given for the reserve count toward those the variant
+    /// deals there. The same seed and configuration always deal the same game.
+    pub fn with_config(variant: Variant, seed: u32, config: &DealConfig)
+            -> Result<FreeCell, DealConfigError> {
+        let mut reserve = vec![None; variant.reserve_slots()];
+
+        if config.reserve.len() > reserve.len() {
+            return Err(DealConfigError::TooManyReserve);
+        }
+
+        for (r, &card) in reserve.iter_mut().zip(&config.reserve) {
+            *r = Some(card);
+        }
 
-        let slot_a = &self.tableau[a];
-        let slot_b = &self.tableau[b];
+        let mut fc = FreeCell{
+            variant: variant,
+            reserve: reserve,
+            foundation: config.foundation,
+            tableau: vec![Vec::new(); variant.tableau_slots()],
+        };
 
-        assert!(!slot_a.is_empty());
+        let mut deck = new_deck(variant.deck(), seed);
+        deck.retain(|&card| fc.find(card).is_none());
 
-        let mut n_empty = self.tableau.iter()
-            .filter(|t| t.is_empty()).count();
+        let n_reserve = variant.reserve_deal().saturating_sub(config.reserve.len())
+            .min(deck.len());
 
-        if slot_b.is_empty() {
-            n_empty -= 1;
+        for card in deck.drain(..n_reserve) {
+            fc.add_to_reserve(card);
         }
 
-        let n_reserve = self.reserve.iter()
-            .filter(|r| r.is_none()).count();
+        if deck.len() < variant.tableau_slots() {
+            return Err(DealConfigError::TooFewCards);
+        }
+
+        fc.tableau = fill_tableau(deck, variant.tableau_slots());
+        fc.validate().map_err(DealConfigError::Integrity)?;
 
-        min(self.group_size(a),
-            (n_reserve + 1) * 2usize.pow(n_empty as u32))
+        Ok(fc)
     }
-}
 
-fn two_mut_refs<T>(slice: &mut [T], a: usize, b: usize) -> (&mut T, &mut T) {
+    /// Creates a game of the given variant with cards in place as given.
+    ///
+    /// `foundation` holds the top card of each foundation slot, in suit order.
+    ///
+    /// # Panics
+    ///
+    /// If the number of reserve or tableau slots does not match the variant.
+    pub fn from_layout(variant: Variant, reserve: Vec<Option<Card>>,
+            foundation: [Option<Card>; FOUNDATION_SLOTS],
+            tableau: Vec<Vec<Card>>) -> FreeCell {
+        assert_eq!(reserve.len(), variant.reserve_slots(),
+            "wrong number of reserve slots");
+        assert_eq!(tableau.len(), variant.tableau_slots(),
+            "wrong number of tableau slots");
+
+        FreeCell{
+            variant: variant,
+            reserve: reserve,
+            foundation: foundation,
+            tableau: tableau,
+        }
+    }
+
+    /// Copies the game state into `dst`, reusing its allocations.
+    pub fn clone_into(&self, dst: &mut FreeCell) {
+        dst.variant = self.variant;
+        dst.reserve.clone_from(&self.reserve);
+        dst.foundation = self.foundation;
+
+        dst.tableau.truncate(self.tableau.len());
+
+        for (d, t) in dst.tableau.iter_mut().zip(&self.tableau) {
+            d.clear();
+            d.extend_from_slice(t);
+        }
+
+        let n = dst.tableau.len();
+        dst.tableau.extend(self.tableau[n..].iter().cloned());
+    }
+
+    /// Returns the variant whose rules govern the game.
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Returns the cards in play.
+    pub fn deck(&self) -> Deck {
+        self.variant.deck()
+    }
+
+    /// Checks that the board has the slots of its variant
+    /// and that each card of the deck appears exactly once.
+    ///
+    /// A foundation slot holding a card accounts for the cards of that suit
+    /// from ace up to that card.
+    pub fn validate(&self) -> Result<(), IntegrityError> {
+        if self.reserve.len() != self.variant.reserve_slots() ||
+                self.tableau.len() != self.variant.tableau_slots() {
+            return Err(IntegrityError::SlotCount);
+        }
+
+        let deck = self.deck();
+        let mut seen = [[false; NUM_FACES]; NUM_SUITS];
+
+        {
+            let mut mark = |card: Card| {
+                if !deck.contains(card) {
+                    return Err(IntegrityError::InvalidCard(card));
+                }
+
+                let slot = &mut seen[card.suit.as_index()][card.value.0 as usize - 1];
+
+                if *slot {
+                    return Err(IntegrityError::Duplicate(card));
+                }
+
+                *slot = true;
+                Ok(())
+            };
+
+            for (&suit, f) in SUITS.iter().zip(&self.foundation) {
+                if let Some(top) = *f {
+                    if top.suit != suit {
+                        return Err(IntegrityError::WrongSuit(suit, top));
+                    }
+
+                    if !deck.contains(top) {
+                        return Err(IntegrityError::InvalidCard(top));
+                    }
+
+                    for v in ACE..=top.value.0 {
+                        mark(Card::new(suit, Face(v)))?;
+                    }
+                }
+            }
+
+            for &card in self.reserve.iter().flatten() {
+                mark(card)?;
+            }
+
+            for &card in self.tableau.iter().flatten() {
+                mark(card)?;
+            }
+        }
+
+        for card in deck.cards() {
+            if !seen[card.suit.as_index()][card.value.0 as usize - 1] {
+                return Err(IntegrityError::Missing(card));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns a hash of the exact board, which is the same between runs,
+    /// platforms, and versions of this crate.
+    ///
+    /// The hash is the 64-bit FNV-1a hash of `to_compact_string`.
+    /// It serves to detect corrupted or altered records of a game;
+    /// it is not a cryptographic hash, and a board with a given hash
+    /// is easily forged.
+    pub fn canonical_hash(&self) -> u64 {
+        self.to_compact_string().bytes().fold(FNV_OFFSET, |h, b| {
+            (h ^ b as u64).wrapping_mul(FNV_PRIME)
+        })
+    }
+
+    /// Writes the board on one line, to be read by `from_compact_string`.
+    ///
+    /// The string holds the variant key, the reserve slots, the foundation
+    /// slots in suit order, and each tableau column from the bottom card,
+    /// separated by `/`. Each card is written as a two-character code,
+    /// such as `TS`, and an empty slot as `--`.
+    pub fn to_compact_string(&self) -> String {
+        let slot = |s: &Option<Card>| s.map_or_else(|| EMPTY_CODE.to_owned(), |c| c.code());
+
+        let mut parts = vec![
+            self.variant.key().to_owned(),
+            self.reserve.iter().map(slot).collect(),
+            self.foundation.iter().map(slot).collect(),
+        ];
+
+        parts.extend(self.tableau.iter()
+            .map(|t| t.iter().map(|c| c.code()).collect()));
+
+        parts.join("/")
+    }
+
+    /// Renders the board as plain text over several lines, for logging
+    /// and debugging: the variant name, the reserve and foundation slots,
+    /// then the tableau, with columns numbered from `1`.
+    ///
+    /// Cards are written as two-character codes, such as `TS`,
+    /// and empty reserve and foundation slots as `--`.
+    pub fn pretty_print(&self) -> String {
+        let slots = |slots: &[Option<Card>]| slots.iter()
+            .map(|s| s.map_or_else(|| EMPTY_CODE.to_owned(), |c| c.code()))
+            .collect::<Vec<_>>().join(" ");
+
+        let mut out = format!("{}\n", self.variant.name());
+
+        out.push_str(&format!("Reserve    {}\n", slots(&self.reserve)));
+        out.push_str(&format!("Foundation {}\n", slots(&self.foundation)));
+
+        for i in 0..self.tableau.len() {
+            out.push_str(&format!("{:>3}", i + 1));
+        }
+        out.push('\n');
+
+        let depth = self.tableau.iter().map(|t| t.len()).max().unwrap_or(0);
+
+        for row in 0..depth {
+            let line = self.tableau.iter()
+                .map(|t| match t.get(row) {
+                    Some(c) => format!(" {}", c.code()),
+                    None => "   ".to_owned()
+                })
+                .collect::<String>();
+
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+
+        out
+    }
+
+    /// Reads a board written by `to_compact_string`,
+    /// checking that it holds each card of the deck exactly once.
+    pub fn from_compact_string(s: &str) -> Result<FreeCell, ParseBoardError> {
+        fn cards(s: &str) -> Result<Vec<Option<Card>>, ParseBoardError> {
+            if s.len() % 2 != 0 || !s.is_ascii() {
+                return Err(ParseBoardError::Syntax);
+            }
+
+            (0..s.len()).step_by(2).map(|i| match &s[i..i + 2] {
+                EMPTY_CODE => Ok(None),
+                code => Card::from_code(code).map(Some).ok_or(ParseBoardError::Syntax)
+            }).collect()
+        }
+
+        let mut parts = s.trim().split('/');
+
+        let variant = parts.next().ok_or(ParseBoardError::Syntax)?;
+        let variant = Variant::from_key(variant).ok_or(ParseBoardError::UnknownVariant)?;
+
+        let reserve = cards(parts.next().ok_or(ParseBoardError::Syntax)?)?;
+        let found = cards(parts.next().ok_or(ParseBoardError::Syntax)?)?;
+
+        let tableau = parts.map(|t| cards(t)?.into_iter()
+                .collect::<Option<Vec<_>>>().ok_or(ParseBoardError::Syntax))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if reserve.len() != variant.reserve_slots() ||
+                tableau.len() != variant.tableau_slots() {
+            return Err(ParseBoardError::WrongSlotCount);
+        }
+
+        let mut foundation = [None; FOUNDATION_SLOTS];
+
+        if found.len() != foundation.len() {
+            return Err(ParseBoardError::WrongSlotCount);
+        }
+        foundation.copy_from_slice(&found);
+
+        let fc = FreeCell::from_layout(variant, reserve, foundation, tableau);
+
+        fc.validate().map_err(ParseBoardError::Integrity)?;
+
+        Ok(fc)
+    }
+
+    /// Reads a board of the given variant in the layout used by fc-solve,
+    /// checking that it holds each card of the deck exactly once.
+    ///
+    /// The optional `Foundations:` line names the top rank of each suit,
+    /// as in `H-5 C-0 D-A S-2`; the optional `Freecells:` line lists
+    /// reserve cards, with `-` for an empty slot. Each remaining line holds
+    /// one tableau column from the bottom card, optionally preceded by `:`.
+    /// Missing reserve slots and tableau columns are empty.
+    pub fn from_fc_solve_str(variant: Variant, s: &str) -> Result<FreeCell, ParseBoardError> {
+        fn card(s: &str) -> Result<Card, ParseBoardError> {
+            Card::from_code(s).ok_or(ParseBoardError::Syntax)
+        }
+
+        let mut reserve = Vec::new();
+        let mut foundation = [None; FOUNDATION_SLOTS];
+        let mut tableau = Vec::new();
+
+        for line in s.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            if let Some(rest) = strip_label(line, "Foundations:") {
+                for word in rest.split_whitespace() {
+                    let mut parts = word.splitn(2, '-');
+                    let suit = parts.next().and_then(|s| s.chars().next())
+                        .and_then(Suit::from_letter).ok_or(ParseBoardError::Syntax)?;
+
+                    foundation[suit.as_index()] = match parts.next() {
+                        Some("0") => None,
+                        Some(rank) => Some(Card::new(suit,
+                            Face::from_code(rank).ok_or(ParseBoardError::Syntax)?)),
+                        None => return Err(ParseBoardError::Syntax)
+                    };
+                }
+            } else if let Some(rest) = strip_label(line, "Freecells:") {
+                for word in rest.split_whitespace() {
+                    reserve.push(match word {
+                        "-" | EMPTY_CODE => None,
+                        code => Some(card(code)?)
+                    });
+                }
+            } else {
+                let line = line.trim_start_matches(':');
+
+                tableau.push(line.split_whitespace()
+                    .map(card).collect::<Result<Vec<_>, _>>()?);
+            }
+        }
+
+        if reserve.len() > variant.reserve_slots() ||
+                tableau.len() > variant.tableau_slots() {
+            return Err(ParseBoardError::WrongSlotCount);
+        }
+
+        reserve.resize(variant.reserve_slots(), None);
+        tableau.resize(variant.tableau_slots(), Vec::new());
+
+        let fc = FreeCell::from_layout(variant, reserve, foundation, tableau);
+
+        fc.validate().map_err(ParseBoardError::Integrity)?;
+
+        Ok(fc)
+    }
+
+    /// Returns whether `card` may be placed atop `other` on the tableau.
+    pub fn can_stack(&self, card: Card, other: Card) -> bool {
+        if self.variant.build_by_suit() {
+            card.suit == other.suit && card.value.next() == Some(other.value)
+        } else {
+            card.can_top(other)
+        }
+    }
+
+    pub fn can_move_to_tableau(&self, card: Card, pos: usize) -> bool {
+        let slot = &self.tableau[pos];
+
+        match slot.last() {
+            Some(&top) => self.can_stack(card, top),
+            None => !self.variant.kings_only() || card.value.0 == self.deck().max_rank
+        }
+    }
+
+    /// Returns the rank which may next be placed on the foundation of
+    /// the given suit: an ace, if it is empty; or `None`, if it is complete
+    /// or the suit is not in play.
+    pub fn next_foundation_rank(&self, suit: Suit) -> Option<Face> {
+        let deck = self.deck();
+
+        if !deck.has_suit(suit) {
+            return None;
+        }
+
+        let next = match self.foundation(suit) {
+            Some(top) => top.value.next()?,
+            None => Face(ACE)
+        };
+
+        if next.0 <= deck.max_rank {
+            Some(next)
+        } else {
+            None
+        }
+    }
+
+    pub fn can_move_to_foundation(&self, card: Card) -> bool {
+        let slot = self.foundation(card.suit);
+
+        card.can_succeed(slot)
+    }
+
+    /// Returns whether a card may be moved to foundation legally and safely,
+    /// as by automatic foundation moves.
+    pub fn should_move_to_foundation(&self, card: Card) -> bool {
+        self.can_move_to_foundation(card) && self.is_safe_foundation_move(card)
+    }
+
+    /// Returns whether moving the card to foundation is safe: that is,
+    /// whether no card which might yet be placed on it would need it
+    /// to remain in play. Legality of the move is not considered.
+    ///
+    /// When building by alternate colors, a card is safe if its rank is
+    /// at most two above the lowest foundation of the opposite color
+    /// and at most three above the lowest of its own color.
+    pub fn is_safe_foundation_move(&self, card: Card) -> bool {
+        // When building by suit, no tableau card can ever be placed
+        // on a card which may be moved to foundation.
+        if self.variant.build_by_suit() {
+            return true;
+        }
+
+        let deck = self.deck();
+        // A suit out of play holds no cards to be placed on
+        let top = |suit| if deck.has_suit(suit) {
+            self.foundation(suit).map_or(0, |c: Card| c.value.0)
+        } else {
+            deck.max_rank
+        };
+
+        let club_v =    top(Suit::Club);
+        let space_v =   top(Suit::Spade);
+        let diamond_v = top(Suit::Diamond);
+        let heart_v =   top(Suit::Heart);
+
+        let min_black = min(club_v, space_v);
+        let min_red = min(diamond_v, heart_v);
+
+        if card.suit.color() == Color::Black {
+            card.value.0 <= min(min_black + 3, min_red + 2)
+        } else {
+            card.value.0 <= min(min_red + 3, min_black + 2)
+        }
+    }
+
+    /// Returns whether any reserve slots are vacant.
+    pub fn reserve_free(&self) -> bool {
+        self.reserve.iter().any(|r| r.is_none())
+    }
+
+    /// Returns whether every card in the deck has been moved to foundation.
+    pub fn game_over(&self) -> bool {
+        self.foundation_total() == self.deck().len()
+    }
+
+    pub fn add_to_foundation(&mut self, card: Card) {
+        self.assert_free(card);
+        assert!(self.can_move_to_foundation(card));
+
+        let slot = self.foundation_mut(card.suit);
+        *slot = Some(card);
+    }
+
+    /// Removes and returns the top foundation card of the given suit.
+    pub fn remove_from_foundation(&mut self, suit: Suit) -> Option<Card> {
+        let top = self.foundation(suit)?;
+
+        *self.foundation_mut(suit) = top.value.prev().map(|v| Card::new(suit, v));
+        Some(top)
+    }
+
+    pub fn add_to_tableau(&mut self, card: Card, pos: usize) {
+        self.assert_free(card);
+        assert!(self.can_move_to_tableau(card, pos));
+
+        self.tableau[pos].push(card);
+    }
+
+    pub fn move_tableau_group(&mut self, a: usize, b: usize, n: usize) {
+        assert!(n != 0);
+        assert!(a != b);
+        assert!(n <= self.move_capacity(a, b));
+
+        let (a, b) = two_mut_refs(&mut self.tableau, a, b);
+
+        let start = a.len() - n;
+        b.extend(a.drain(start..));
+    }
+
+    /// Adds a card to the lowest-numbered vacant reserve slot.
+    ///
+    /// Other reserve cards keep their positions.
+    pub fn add_to_reserve(&mut self, card: Card) {
+        let pos = self.free_reserve_slot().expect("reserve is full");
+        self.add_to_reserve_at(card, pos);
+    }
+
+    /// Adds a card to the given reserve slot, which must be vacant.
+    pub fn add_to_reserve_at(&mut self, card: Card, pos: usize) {
+        self.assert_free(card);
+        assert!(self.reserve[pos].is_none(), "reserve slot is occupied");
+
+        self.reserve[pos] = Some(card);
+    }
+
+    /// Returns the index of the first vacant reserve slot.
+    pub fn free_reserve_slot(&self) -> Option<usize> {
+        self.reserve.iter().position(|r| r.is_none())
+    }
+
+    /// Automatically moves to foundation up to `n` cards.
+    /// Returns whether any cards were moved.
+    pub fn sweep_step(&mut self, n: u32) -> bool {
+        self.sweep_step_except(n, &[])
+    }
+
+    /// Automatically moves to foundation up to `n` cards,
+    /// leaving in place the cards in `hold`.
+    /// Returns whether any cards were moved.
+    pub fn sweep_step_except(&mut self, n: u32, hold: &[Card]) -> bool {
+        let mut left = n;
+
+        for (n, r) in self.reserve.clone().iter().cloned().enumerate() {
+            if let Some(c) = r {
+                if self.should_move_to_foundation(c) && !hold.contains(&c) {
+                    self.remove_reserve(n);
+                    self.add_to_foundation(c);
+
+                    left -= 1;
+                    if left == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+
+        if left != 0 {
+            let sweep = self.tableau.iter().cloned().enumerate()
+                .filter_map(|(i, t)| t.last().map(|&c| (i, c)))
+                .filter(|&(_, c)| self.should_move_to_foundation(c) && !hold.contains(&c))
+                .map(|(i, _)| i).collect::<Vec<_>>();
+
+            for i in sweep.into_iter().take(left as usize) {
+                let c = self.pop_tableau(i);
+                self.add_to_foundation(c);
+
+                left -= 1;
+            }
+        }
+
+        left != n
+    }
+
+    /// Returns a sequence of foundation moves which wins the game,
+    /// if the game can be won by foundation moves alone.
+    pub fn finish_moves(&self) -> Option<Vec<Move>> {
+        let mut fc = self.clone();
+        let mut moves = Vec::new();
+
+        while !fc.game_over() {
+            // Moving a card to foundation never prevents another from following,
+            // so any available foundation move may be taken.
+            let mv = fc.next_foundation_move()?;
+
+            fc.apply_move(mv).expect("legal move failed");
+            moves.push(mv);
+        }
+
+        Some(moves)
+    }
+
+    /// Returns the first available move of a card to foundation.
+    fn next_foundation_move(&self) -> Option<Move> {
+        let reserve = self.reserve.iter().position(|r| match *r {
+            Some(c) => self.can_move_to_foundation(c),
+            None => false
+        });
+
+        if let Some(pos) = reserve {
+            return Some(Move::ReserveToFoundation(pos));
+        }
+
+        self.tableau.iter().position(|t| match t.last() {
+            Some(&c) => self.can_move_to_foundation(c),
+            None => false
+        }).map(Move::TableauToFoundation)
+    }
+
+    /// Returns the location of a card.
+    pub fn find(&self, card: Card) -> Option<Position> {
+        if let Some(pos) = self.reserve.iter().position(|&r| r == Some(card)) {
+            return Some(Position::Reserve(pos));
+        }
+
+        if self.foundation(card.suit).map_or(false, |top| card.value <= top.value) {
+            return Some(Position::Foundation);
+        }
+
+        self.tableau.iter().enumerate()
+            .filter_map(|(i, t)| t.iter().position(|&c| c == card)
+                .map(|j| Position::Tableau(i, j)))
+            .next()
+    }
+
+    /// Returns the card which a move would carry;
+    /// for a move of several cards, the lowest in the group.
+    pub fn moved_card(&self, mv: Move) -> Option<Card> {
+        match mv {
+            Move::ReserveToFoundation(a) |
+            Move::ReserveToTableau(a, _) => self.reserve(a),
+            Move::TableauToFoundation(a) |
+            Move::TableauToReserve(a) => self.tableau(a).last().cloned(),
+            Move::TableauToTableau(a, _, n) => {
+                let t = self.tableau(a);
+                t.len().checked_sub(n).map(|i| t[i])
+            }
+        }
+    }
+
+    /// Returns the indices of empty tableau slots.
+    pub fn empty_columns<'a>(&'a self) -> impl Iterator<Item = usize> + 'a {
+        self.tableau.iter().enumerate()
+            .filter(|&(_, t)| t.is_empty())
+            .map(|(i, _)| i)
+    }
+
+    /// Returns the number of empty tableau slots.
+    pub fn empty_column_count(&self) -> usize {
+        self.empty_columns().count()
+    }
+
+    /// Returns the number of reserve slots.
+    pub fn reserve_count(&self) -> usize { self.reserve.len() }
+
+    /// Returns the number of occupied reserve slots.
+    pub fn reserve_occupied(&self) -> usize {
+        self.reserve.iter().filter(|r| r.is_some()).count()
+    }
+
+    /// Returns the total number of cards on all foundations.
+    pub fn foundation_total(&self) -> usize {
+        self.foundation.iter()
+            .map(|f| f.map_or(0, |c| c.value.0 as usize)).sum()
+    }
+
+    /// Returns the number of foundation slots.
+    pub fn foundation_count(&self) -> usize { self.foundation.len() }
+
+    /// Returns the number of tableau slots.
+    pub fn tableau_count(&self) -> usize { self.tableau.len() }
+
+    /// Removes the card from the given reserve slot, leaving the slot vacant.
+    ///
+    /// Other reserve cards keep their positions.
+    pub fn remove_reserve(&mut self, pos: usize) -> Card {
+        self.reserve[pos].take().expect("reserve is empty")
+    }
+
+    pub fn reserve_slots(&self) -> &[Option<Card>] { &self.reserve }
+
+    /// Returns each reserve slot, with its position.
+    pub fn reserve_iter(&self) -> impl Iterator<Item = (usize, Option<Card>)> + '_ {
+        self.reserve.iter().cloned().enumerate()
+    }
+
+    pub fn reserve(&self, pos: usize) -> Option<Card> {
+        self.reserve[pos]
+    }
+
+    pub fn tableau_slots(&self) -> &[Vec<Card>] { &self.tableau }
+
+    pub fn tableau(&self, pos: usize) -> &[Card] {
+        &self.tableau[pos]
+    }
+
+    pub fn tableau_mut(&mut self, pos: usize) -> &mut Vec<Card> {
+        &mut self.tableau[pos]
+    }
+
+    pub fn pop_tableau(&mut self, pos: usize) -> Card {
+        self.tableau[pos].pop().expect("tableau is empty")
+    }
+
+    fn assert_free(&self, card: Card) {
+        assert!(self.reserve.iter().all(|&r| r != Some(card)),
+            "card is not free; found in reserve");
+        assert!(!self.foundation.iter().any(
+            |r| r.map_or(false, |r| card.is_lower(r))),
+            "card is not free; found in foundation");
+        assert!(self.tableau.iter().all(|t| !t.contains(&card)),
+            "card is not free; found in tableau");
+    }
+
+    pub fn foundation_slots(&self) -> &[Option<Card>] { &self.foundation }
+
+    /// Returns each foundation slot, with the suit it holds,
+    /// in `SUITS` order.
+    pub fn foundation_iter(&self) -> impl Iterator<Item = (Suit, Option<Card>)> + '_ {
+        Suit::all().map(move |suit| (suit, self.foundation(suit)))
+    }
+
+    pub fn foundation(&self, suit: Suit) -> Option<Card> {
+        self.foundation[suit.as_index()]
+    }
+
+    fn foundation_mut(&mut self, suit: Suit) -> &mut Option<Card> {
+        &mut self.foundation[suit.as_index()]
+    }
+
+    pub fn group_size(&self, pos: usize) -> usize {
+        let slot = &self.tableau[pos];
+
+        if slot.is_empty() {
+            return 0;
+        }
+        if slot.len() == 1 {
+            return 1;
+        }
+
+        let mut n = 1;
+
+        let pairs = slot.iter().zip(slot[1..].iter());
+
+        for (&a, &b) in pairs.rev() {
+            if self.can_stack(b, a) {
+                n += 1;
+            } else {
+                break;
+            }
+        }
+
+        n
+    }
+
+    /// Returns the number of cards a move from tableau slot `a`
+    /// to tableau slot `b` would carry.
+    ///
+    /// When `b` is empty, as many cards as possible are moved.
+    pub fn tableau_move_size(&self, a: usize, b: usize) -> Result<usize, MoveError> {
+        let tab_a = &self.tableau[a];
+
+        if tab_a.is_empty() {
+            return Err(MoveError::EmptySource);
+        }
+
+        if self.tableau[b].is_empty() && !self.variant.kings_only() {
+            return Ok(self.move_capacity(a, b));
+        }
+
+        let n = tab_a.len();
+        let size = self.group_size(a);
+        let cap = if self.tableau[b].is_empty() {
+            self.max_group_to_empty()
+        } else {
+            self.max_group_to_nonempty()
+        };
+
+        for i in 1..size + 1 {
+            let c = tab_a[n - i];
+            if self.can_move_to_tableau(c, b) {
+                if i > cap {
+                    return Err(MoveError::NotEnoughSpace);
+                } else {
+                    return Ok(i);
+                }
+            }
+        }
+
+        Err(MoveError::InvalidDestination)
+    }
+
+    /// Returns all moves which may be made from the current state.
+    ///
+    /// Moves of a group to an empty tableau slot are given
+    /// for each possible group size.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let to_empty = self.max_group_to_empty();
+
+        for (i, r) in self.reserve.iter().enumerate() {
+            if let Some(c) = *r {
+                if self.can_move_to_foundation(c) {
+                    moves.push(Move::ReserveToFoundation(i));
+                }
+
+                for b in 0..self.tableau.len() {
+                    if self.can_move_to_tableau(c, b) {
+                        moves.push(Move::ReserveToTableau(i, b));
+                    }
+                }
+            }
+        }
+
+        for a in 0..self.tableau.len() {
+            let top = match self.tableau[a].last() {
+                Some(&c) => c,
+                None => continue
+            };
+
+            if self.can_move_to_foundation(top) {
+                moves.push(Move::TableauToFoundation(a));
+            }
+
+            if self.reserve_free() {
+                moves.push(Move::TableauToReserve(a));
+            }
+
+            for b in 0..self.tableau.len() {
+                if a == b {
+                    continue;
+                }
+
+                if self.tableau[b].is_empty() {
+                    let tab_a = &self.tableau[a];
+
+                    for n in 1..min(self.group_size(a), to_empty) + 1 {
+                        if self.can_move_to_tableau(tab_a[tab_a.len() - n], b) {
+                            moves.push(Move::TableauToTableau(a, b, n));
+                        }
+                    }
+                } else if let Ok(n) = self.tableau_move_size(a, b) {
+                    moves.push(Move::TableauToTableau(a, b, n));
+                }
+            }
+        }
+
+        moves
+    }
+
+    /// Returns a reasonable move, chosen at once by simple rules
+    /// rather than by searching for a win; or `None`, if no move is useful.
+    ///
+    /// Moves are preferred in this order:
+    ///
+    /// 1. A card which may safely be moved to foundation
+    /// 2. A move which empties a tableau column, other than to reserve
+    /// 3. A move which exposes a card which may then be moved to foundation
+    /// 4. A reserve card placed on a tableau card
+    /// 5. Any other move to foundation
+    /// 6. A move on the tableau which exposes another card,
+    ///    or a reserve card placed in an empty column
+    ///
+    /// A move which covers the next card needed on a foundation is made
+    /// only if no other is useful. A move which only carries a card from one
+    /// card on which it may be placed to another is never made.
+    pub fn best_move(&self) -> Option<Move> {
+        let mut best = None;
+
+        for mv in self.legal_moves() {
+            if let Some(rank) = self.move_rank(mv) {
+                if best.map_or(true, |(r, _)| rank > r) {
+                    best = Some((rank, mv));
+                }
+            }
+        }
+
+        best.map(|(_, mv)| mv)
+    }
+
+    /// Ranks a move for `best_move`, higher ranks being preferred,
+    /// or returns `None` if the move is of no use.
+    fn move_rank(&self, mv: Move) -> Option<u32> {
+        let card = self.moved_card(mv)?;
+
+        // Source tableau column and the number of cards left in it
+        let source = match mv {
+            Move::TableauToFoundation(a) | Move::TableauToReserve(a) =>
+                Some((a, self.tableau[a].len() - 1)),
+            Move::TableauToTableau(a, _, n) => Some((a, self.tableau[a].len() - n)),
+            _ => None
+        };
+        let dest = match mv {
+            Move::ReserveToTableau(_, b) | Move::TableauToTableau(_, b, _) => Some(b),
+            _ => None
+        };
+
+        let mut next = self.clone();
+        next.apply_move(mv).ok()?;
+
+        let empties = source.map_or(false, |(_, rest)| rest == 0);
+        let exposes = source.map_or(false, |(a, rest)| rest != 0 &&
+            next.tableau[a].last().map_or(false, |&c| next.can_move_to_foundation(c)));
+        // Whether the moved cards already rest on a card which suits them
+        let settled = source.map_or(false, |(a, rest)| rest != 0 &&
+            self.can_stack(self.tableau[a][rest], self.tableau[a][rest - 1]));
+        let to_empty = dest.map_or(false, |b| self.tableau[b].is_empty());
+
+        let mut rank = match mv {
+            _ if mv.is_to_foundation() && self.is_safe_foundation_move(card) => 6,
+            _ if empties && dest.is_some() && !to_empty => 5,
+            _ if exposes => 4,
+            Move::ReserveToTableau(..) if !to_empty => 3,
+            _ if mv.is_to_foundation() => 2,
+            Move::ReserveToTableau(..) => 1,
+            Move::TableauToTableau(..) if !empties && !settled => 1,
+            _ => return None
+        };
+
+        // Covering the next card needed on a foundation is a last resort
+        let buries = dest.map_or(false, |b| self.tableau[b].iter()
+            .any(|c| self.next_foundation_rank(c.suit) == Some(c.value)));
+
+        if buries {
+            rank = 0;
+        }
+
+        Some(rank)
+    }
+
+    /// Returns the notable events which lead from `prev` to this game,
+    /// as when `prev` is the board before a move and any foundation moves
+    /// that followed it.
+    pub fn events_since(&self, prev: &FreeCell) -> Vec<Event> {
+        let mut events = Vec::new();
+        let max_rank = self.deck().max_rank;
+
+        for (i, (old, new)) in prev.tableau.iter().zip(&self.tableau).enumerate() {
+            if !old.is_empty() && new.is_empty() {
+                events.push(Event::ColumnEmptied(i));
+            }
+        }
+
+        for &suit in &SUITS {
+            let old = prev.foundation(suit).map_or(0, |c| c.value.0);
+            let new = self.foundation(suit).map_or(0, |c| c.value.0);
+
+            if old < ACE && new >= ACE {
+                events.push(Event::AceHome(suit));
+            }
+            if old < max_rank && new >= max_rank {
+                events.push(Event::SuitComplete(suit));
+            }
+        }
+
+        if prev.reserve_free() && !self.reserve_free() {
+            events.push(Event::ReserveFilled);
+        }
+
+        events
+    }
+
+    /// Makes the given move, if it is legal.
+    pub fn apply_move(&mut self, mv: Move) -> Result<(), MoveError> {
+        match mv {
+            Move::ReserveToFoundation(a) => {
+                let c = self.reserve[a].ok_or(MoveError::EmptySource)?;
+
+                if !self.can_move_to_foundation(c) {
+                    return Err(MoveError::InvalidDestination);
+                }
+
+                self.remove_reserve(a);
+                self.add_to_foundation(c);
+            }
+            Move::ReserveToTableau(a, b) => {
+                let c = self.reserve[a].ok_or(MoveError::EmptySource)?;
+
+                if !self.can_move_to_tableau(c, b) {
+                    return Err(MoveError::InvalidDestination);
+                }
+
+                self.remove_reserve(a);
+                self.add_to_tableau(c, b);
+            }
+            Move::TableauToFoundation(a) => {
+                let c = *self.tableau[a].last().ok_or(MoveError::EmptySource)?;
+
+                if !self.can_move_to_foundation(c) {
+                    return Err(MoveError::InvalidDestination);
+                }
+
+                self.pop_tableau(a);
+                self.add_to_foundation(c);
+            }
+            Move::TableauToReserve(a) => {
+                if self.tableau[a].is_empty() {
+                    return Err(MoveError::EmptySource);
+                }
+                if !self.reserve_free() {
+                    return Err(MoveError::ReserveFull);
+                }
+
+                let c = self.pop_tableau(a);
+                self.add_to_reserve(c);
+            }
+            Move::TableauToTableau(a, b, n) => {
+                let tab_a = &self.tableau[a];
+
+                if tab_a.is_empty() {
+                    return Err(MoveError::EmptySource);
+                }
+                if a == b || n == 0 || n > self.group_size(a) {
+                    return Err(MoveError::InvalidDestination);
+                }
+
+                let c = tab_a[tab_a.len() - n];
+
+                if !self.can_move_to_tableau(c, b) {
+                    return Err(MoveError::InvalidDestination);
+                }
+                if n > self.move_capacity(a, b) {
+                    return Err(MoveError::NotEnoughSpace);
+                }
+
+                self.move_tableau_group(a, b, n);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn move_capacity(&self, a: usize, b: usize) -> usize {
+        assert!(a != b);
+        assert!(!self.tableau[a].is_empty());
+
+        let max = if self.tableau[b].is_empty() {
+            self.max_group_to_empty()
+        } else {
+            self.max_group_to_nonempty()
+        };
+
+        min(self.group_size(a), max)
+    }
+
+    /// Returns the largest number of cards which may be moved together
+    /// onto a nonempty tableau slot, using free reserve slots
+    /// and empty tableau slots as temporary space.
+    pub fn max_group_to_nonempty(&self) -> usize {
+        self.max_group(self.empty_column_count())
+    }
+
+    /// Returns the largest number of cards which may be moved together
+    /// into an empty tableau slot, using free reserve slots
+    /// and the remaining empty tableau slots as temporary space.
+    pub fn max_group_to_empty(&self) -> usize {
+        self.max_group(self.empty_column_count().saturating_sub(1))
+    }
+
+    fn max_group(&self, mut n_empty: usize) -> usize {
+        // Empty slots which accept only kings cannot hold part of a group
+        if self.variant.kings_only() {
+            n_empty = 0;
+        }
+
+        let n_reserve = self.reserve_count() - self.reserve_occupied();
+
+        (n_reserve + 1) * 2usize.pow(n_empty as u32)
+    }
+}
+
+fn two_mut_refs<T>(slice: &mut [T], a: usize, b: usize) -> (&mut T, &mut T) {
     assert!(a != b);
 
     if a < b {
@@ -379,3 +1738,485 @@ fn two_mut_refs<T>(slice: &mut [T], a: usize, b: usize) -> (&mut T, &mut T) {
         (&mut aa[0], &mut bb[b])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Foundations at five, with moves of the top cards of columns 1 and 3
+    /// leading to a chain of foundation moves
+    const SWEEP_BOARD: &str = "quick_free_cell/--------/5C5D5H5S/\
+        6C7H/6D7S/6H7C/6S7D/8C/8D/8H/8S";
+
+    fn card(code: &str) -> Card {
+        Card::from_code(code).unwrap()
+    }
+
+    fn board(s: &str) -> FreeCell {
+        FreeCell::from_compact_string(s).unwrap()
+    }
+
+    #[test]
+    fn game_over_last_card() {
+        let mut fc = board("quick_free_cell/--------/8C8D8H7S/8S///////");
+
+        assert!(!fc.game_over());
+        fc.apply_move(Move::TableauToFoundation(0)).unwrap();
+        assert!(fc.game_over());
+    }
+
+    #[test]
+    fn game_over_empty_board() {
+        let fc = FreeCell::from_layout(Variant::FreeCell,
+            vec![None; RESERVE_SLOTS], [None; FOUNDATION_SLOTS],
+            vec![Vec::new(); TABLEAU_SLOTS]);
+
+        assert!(!fc.game_over());
+    }
+
+    #[test]
+    fn move_to_reserve_leftmost() {
+        let mut fc = board("quick_free_cell/--7H----/5C5D5H5S/6C/6D7S/6H7C/6S7D/8C/8D/8H/8S");
+
+        fc.apply_move(Move::TableauToReserve(1)).unwrap();
+        assert_eq!(fc.reserve_slots(), &[Some(card("7S")), Some(card("7H")), None, None]);
+
+        fc.apply_move(Move::TableauToReserve(2)).unwrap();
+        assert_eq!(fc.reserve_slots(),
+            &[Some(card("7S")), Some(card("7H")), Some(card("7C")), None]);
+    }
+
+    #[test]
+    fn move_to_reserve_full() {
+        let mut fc = board("quick_free_cell/7H6D7S6H/5C5D5H5S/6C/7C/6S7D/8C/8D/8H/8S/");
+        let before = fc.to_compact_string();
+
+        assert_eq!(fc.apply_move(Move::TableauToReserve(0)), Err(MoveError::ReserveFull));
+        assert_eq!(fc.to_compact_string(), before);
+    }
+
+    #[test]
+    fn reserve_positions_stable() {
+        let mut fc = board(SWEEP_BOARD);
+
+        fc.apply_move(Move::TableauToReserve(0)).unwrap();
+        fc.apply_move(Move::TableauToReserve(1)).unwrap();
+        assert_eq!(fc.reserve_slots(), &[Some(card("7H")), Some(card("7S")), None, None]);
+
+        // 7H leaves slot 0; 7S stays in slot 1
+        fc.apply_move(Move::ReserveToTableau(0, 7)).unwrap();
+        assert_eq!(fc.reserve_slots(), &[None, Some(card("7S")), None, None]);
+
+        fc.apply_move(Move::TableauToReserve(2)).unwrap();
+        assert_eq!(fc.reserve_slots(), &[Some(card("7C")), Some(card("7S")), None, None]);
+    }
+
+    #[test]
+    fn duplicate_ace_compact() {
+        let s = "quick_free_cell/AC------/5C5D5H5S/6C7H/6D7S/6H7C/6S7D/8C/8D/8H/8S";
+
+        assert_eq!(FreeCell::from_compact_string(s).err(),
+            Some(ParseBoardError::Integrity(IntegrityError::Duplicate(card("AC")))));
+    }
+
+    #[test]
+    fn duplicate_ace_fc_solve() {
+        let s = "Foundations: C-5 D-5 H-5 S-5\n\
+            Freecells: AC\n\
+            6C 7H\n6D 7S\n6H 7C\n6S 7D\n8C\n8D\n8H\n8S\n";
+
+        assert_eq!(FreeCell::from_fc_solve_str(Variant::QuickFreeCell, s).err(),
+            Some(ParseBoardError::Integrity(IntegrityError::Duplicate(card("AC")))));
+    }
+
+    #[test]
+    fn duplicate_ace_deserialized() {
+        // As a save edited by hand would be read
+        let mut fc = board(SWEEP_BOARD);
+        fc.tableau[4].push(card("AC"));
+
+        assert_eq!(fc.validate(), Err(IntegrityError::Duplicate(card("AC"))));
+    }
+
+    #[test]
+    fn validate_slot_count() {
+        let mut fc = board(SWEEP_BOARD);
+        fc.reserve.push(None);
+        assert_eq!(fc.validate(), Err(IntegrityError::SlotCount));
+
+        let mut fc = board(SWEEP_BOARD);
+        fc.tableau.pop();
+        assert_eq!(fc.validate(), Err(IntegrityError::SlotCount));
+    }
+
+    #[test]
+    fn card_codes_round_trip() {
+        let deck = STANDARD_DECK.cards().collect::<Vec<_>>();
+        assert_eq!(deck.len(), 52);
+
+        for c in deck {
+            assert_eq!(Card::from_code(&c.code()), Some(c));
+            assert_eq!(Face::from_code(&c.value.code().to_string()), Some(c.value));
+        }
+    }
+
+    #[test]
+    fn card_codes_ten() {
+        assert_eq!(Card::from_code("10H"), Some(Card::new(Suit::Heart, Face(10))));
+        assert_eq!(Card::from_code("TH"), Some(Card::new(Suit::Heart, Face(10))));
+        assert_eq!(Card::new(Suit::Heart, Face(10)).code(), "TH");
+
+        assert_eq!(Face::from_code("10"), Some(Face(10)));
+        assert_eq!(Face::from_code("t"), Some(Face(10)));
+    }
+
+    #[test]
+    fn card_codes_invalid() {
+        for s in &["", "H", "1H", "11H", "0H", "7X", "7", "AHH", "\u{2665}7"] {
+            assert_eq!(Card::from_code(s), None, "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn face_all_bounds() {
+        let faces = Face::all().collect::<Vec<_>>();
+
+        assert_eq!(faces.len(), NUM_FACES);
+        assert_eq!(faces.first(), Some(&Face(ACE)));
+        assert_eq!(faces.last(), Some(&Face(KING)));
+        assert!(faces.windows(2).all(|w| w[0].next() == Some(w[1])));
+    }
+
+    #[test]
+    fn suit_all_order() {
+        assert_eq!(Suit::all().collect::<Vec<_>>(), SUITS.to_vec());
+        assert!(Suit::all().enumerate().all(|(i, s)| s.as_index() == i));
+    }
+
+    #[test]
+    fn face_next_prev_bounds() {
+        assert_eq!(Face(ACE).prev(), None);
+        assert_eq!(Face(ACE).next(), Some(Face(2)));
+        assert_eq!(Face(2).prev(), Some(Face(ACE)));
+
+        assert_eq!(Face(KING).next(), None);
+        assert_eq!(Face(KING).prev(), Some(Face(QUEEN)));
+        assert_eq!(Face(QUEEN).next(), Some(Face(KING)));
+    }
+
+    #[test]
+    fn face_is_ace_king() {
+        assert!(Face(ACE).is_ace() && !Face(ACE).is_king());
+        assert!(Face(KING).is_king() && !Face(KING).is_ace());
+        assert_eq!(Face::all().filter(|v| v.is_ace() || v.is_king()).count(), 2);
+    }
+
+    #[test]
+    fn card_one_higher_lower_bounds() {
+        assert_eq!(card("AS").one_lower(), None);
+        assert_eq!(card("AS").one_higher(), Some(card("2S")));
+        assert_eq!(card("2S").one_lower(), Some(card("AS")));
+
+        assert_eq!(card("KH").one_higher(), None);
+        assert_eq!(card("KH").one_lower(), Some(card("QH")));
+        assert_eq!(card("QH").one_higher(), Some(card("KH")));
+    }
+
+    #[test]
+    fn card_rank_checks_bounds() {
+        assert!(card("AS").can_succeed(None));
+        assert!(!card("2S").can_succeed(None));
+        assert!(card("2S").can_succeed(Some(card("AS"))));
+        assert!(!card("2H").can_succeed(Some(card("AS"))));
+        assert!(card("KH").can_succeed(Some(card("QH"))));
+
+        assert!(card("QH").can_top(card("KS")));
+        assert!(!card("KH").can_top(card("AS")));
+        assert!(!card("AH").can_top(card("KS")));
+
+        assert!(card("AS").is_lower(card("KS")));
+        assert!(!card("KS").is_lower(card("KS")));
+        assert!(!card("AH").is_lower(card("KS")));
+    }
+
+    /// Returns an otherwise empty board of the given variant
+    /// with the given foundation tops, in suit order.
+    fn foundations(variant: Variant, tops: [u8; FOUNDATION_SLOTS]) -> FreeCell {
+        let mut foundation = [None; FOUNDATION_SLOTS];
+
+        for ((f, &suit), &v) in foundation.iter_mut().zip(&SUITS).zip(&tops) {
+            if v != 0 {
+                *f = Some(Card::new(suit, Face(v)));
+            }
+        }
+
+        FreeCell::from_layout(variant, vec![None; variant.reserve_slots()],
+            foundation, vec![Vec::new(); variant.tableau_slots()])
+    }
+
+    #[test]
+    fn next_foundation_rank_bounds() {
+        let fc = foundations(Variant::FreeCell, [0, 1, QUEEN, KING]);
+
+        assert_eq!(fc.next_foundation_rank(Suit::Club), Some(Face(ACE)));
+        assert_eq!(fc.next_foundation_rank(Suit::Diamond), Some(Face(2)));
+        assert_eq!(fc.next_foundation_rank(Suit::Heart), Some(Face(KING)));
+        assert_eq!(fc.next_foundation_rank(Suit::Spade), None);
+
+        // Ranks above eight are out of play
+        let fc = foundations(Variant::QuickFreeCell, [0, 7, 8, 0]);
+
+        assert_eq!(fc.next_foundation_rank(Suit::Diamond), Some(Face(8)));
+        assert_eq!(fc.next_foundation_rank(Suit::Heart), None);
+    }
+
+    #[test]
+    fn safe_foundation_opposite_color() {
+        // Black cards are limited to two above the lowest red foundation
+        let fc = foundations(Variant::FreeCell, [5, 2, 2, 5]);
+
+        assert!(fc.is_safe_foundation_move(card("4C")));
+        assert!(!fc.is_safe_foundation_move(card("5C")));
+        assert!(fc.is_safe_foundation_move(card("4S")));
+        assert!(!fc.is_safe_foundation_move(card("5S")));
+    }
+
+    #[test]
+    fn safe_foundation_own_color() {
+        // Black cards are limited to three above the lowest black foundation
+        let fc = foundations(Variant::FreeCell, [1, 5, 5, 3]);
+
+        assert!(fc.is_safe_foundation_move(card("4C")));
+        assert!(!fc.is_safe_foundation_move(card("5C")));
+
+        // Red cards are limited by the lower bound of the two
+        assert!(fc.is_safe_foundation_move(card("3H")));
+        assert!(!fc.is_safe_foundation_move(card("4H")));
+    }
+
+    #[test]
+    fn safe_foundation_by_suit() {
+        let fc = foundations(Variant::BakersGame, [1, 5, 5, 3]);
+
+        assert!(fc.is_safe_foundation_move(card("QC")));
+    }
+
+    /// Returns a board of the given variant with `free` vacant reserve slots
+    /// and `empty` empty tableau slots; other slots hold a card each.
+    fn space(variant: Variant, free: usize, empty: usize) -> FreeCell {
+        let mut cards = STANDARD_DECK.cards();
+        let n_reserve = variant.reserve_slots();
+        let n_tableau = variant.tableau_slots();
+
+        let reserve = (0..n_reserve)
+            .map(|i| if i < n_reserve - free { cards.next() } else { None })
+            .collect();
+        let tableau = (0..n_tableau)
+            .map(|i| if i < n_tableau - empty { cards.next().into_iter().collect() }
+                else { Vec::new() })
+            .collect();
+
+        FreeCell::from_layout(variant, reserve, [None; FOUNDATION_SLOTS], tableau)
+    }
+
+    #[test]
+    fn max_group_formulas() {
+        // (f + 1) * 2^e onto a card; (f + 1) * 2^(e - 1) into an empty slot
+        for free in 0..=RESERVE_SLOTS {
+            for empty in 0..=3u32 {
+                let fc = space(Variant::FreeCell, free, empty as usize);
+
+                assert_eq!(fc.max_group_to_nonempty(), (free + 1) * 2usize.pow(empty),
+                    "{} free, {} empty", free, empty);
+
+                if empty != 0 {
+                    assert_eq!(fc.max_group_to_empty(), (free + 1) * 2usize.pow(empty - 1),
+                        "{} free, {} empty", free, empty);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn max_group_kings_only() {
+        // Empty slots which accept only kings do not add to capacity
+        let fc = space(Variant::EightOff, 3, 2);
+
+        assert_eq!(fc.max_group_to_nonempty(), 4);
+        assert_eq!(fc.max_group_to_empty(), 4);
+    }
+
+    /// Returns a board with one vacant reserve slot and one empty column,
+    /// with the given run in column 1 to be moved onto the ten of spades
+    /// in column 2: four cards at most may move together.
+    fn supermove(run: &[&str]) -> FreeCell {
+        let mut tableau = vec![
+            run.iter().map(|c| card(c)).collect(),
+            vec![card("TS")],
+        ];
+        tableau.extend(["QC", "QD", "QH", "QS", "JC"].iter().map(|c| vec![card(c)]));
+        tableau.push(Vec::new());
+
+        FreeCell::from_layout(Variant::FreeCell,
+            vec![Some(card("KC")), Some(card("KD")), Some(card("KH")), None],
+            [None; FOUNDATION_SLOTS], tableau)
+    }
+
+    #[test]
+    fn supermove_at_capacity() {
+        let mut fc = supermove(&["9H", "8S", "7H", "6S"]);
+
+        assert_eq!(fc.max_group_to_nonempty(), 4);
+        assert_eq!(fc.tableau_move_size(0, 1), Ok(4));
+
+        fc.apply_move(Move::TableauToTableau(0, 1, 4)).unwrap();
+        assert!(fc.tableau(0).is_empty());
+        assert_eq!(fc.tableau(1).len(), 5);
+    }
+
+    #[test]
+    fn supermove_over_capacity() {
+        let mut fc = supermove(&["9H", "8S", "7H", "6S", "5H"]);
+
+        assert_eq!(fc.tableau_move_size(0, 1), Err(MoveError::NotEnoughSpace));
+        assert_eq!(fc.apply_move(Move::TableauToTableau(0, 1, 5)),
+            Err(MoveError::NotEnoughSpace));
+        assert_eq!(fc.tableau(0).len(), 5);
+    }
+
+    #[test]
+    fn empty_columns_known() {
+        let fc = board("quick_free_cell/--------/5C5D5H5S/6C7H/6D7S6H/7C//6S7D/8C8D8H//8S");
+
+        assert_eq!(fc.empty_columns().collect::<Vec<_>>(), vec![3, 6]);
+        assert_eq!(fc.empty_column_count(), 2);
+
+        assert_eq!(board(SWEEP_BOARD).empty_column_count(), 0);
+    }
+
+    #[test]
+    fn foundation_iter_suits() {
+        let fc = foundations(Variant::FreeCell, [3, 0, KING, ACE]);
+
+        assert_eq!(fc.foundation_iter().collect::<Vec<_>>(), vec![
+            (Suit::Club, Some(card("3C"))),
+            (Suit::Diamond, None),
+            (Suit::Heart, Some(card("KH"))),
+            (Suit::Spade, Some(card("AS"))),
+        ]);
+
+        for (i, (suit, top)) in fc.foundation_iter().enumerate() {
+            assert_eq!(suit.as_index(), i);
+            assert_eq!(top, fc.foundation_slots()[i]);
+        }
+    }
+
+    #[test]
+    fn move_classifiers() {
+        let moves = [
+            Move::ReserveToFoundation(0),
+            Move::ReserveToTableau(1, 2),
+            Move::TableauToFoundation(3),
+            Move::TableauToReserve(4),
+            Move::TableauToTableau(5, 6, 3),
+        ];
+
+        let classes = moves.iter().map(|mv| (
+            mv.is_to_foundation(),
+            mv.is_from_reserve(),
+            mv.is_tableau_to_tableau(),
+            mv.card_count(),
+        )).collect::<Vec<_>>();
+
+        assert_eq!(classes, vec![
+            (true, true, false, 1),
+            (false, true, false, 1),
+            (true, false, false, 1),
+            (false, false, false, 1),
+            (false, false, true, 3),
+        ]);
+    }
+
+    #[test]
+    fn finish_moves_through_reserve() {
+        // 7C waits on 6C in the reserve
+        let fc = board("quick_free_cell/6C------/5C5D5H5S/\
+            8C7C/7D6D/8D/7H6H/8H/7S6S/8S/");
+        let moves = fc.finish_moves().unwrap();
+
+        assert_eq!(moves.len(), 12);
+        assert_eq!(moves[0], Move::ReserveToFoundation(0));
+
+        let mut fc = fc;
+        for mv in moves {
+            fc.apply_move(mv).unwrap();
+        }
+        assert!(fc.game_over());
+    }
+
+    #[test]
+    fn finish_moves_blocked() {
+        // 7C covers 6C
+        let fc = board("quick_free_cell/--------/5C5D5H5S/\
+            8C6C7C/7D6D/8D/7H6H/8H/7S6S/8S/");
+
+        assert_eq!(fc.finish_moves(), None);
+    }
+
+    #[test]
+    fn sort_deck_suit_major() {
+        let mut deck = new_deck(STANDARD_DECK, 617);
+        assert_ne!(deck, STANDARD_DECK.cards().collect::<Vec<_>>());
+
+        deck.sort();
+        assert_eq!(deck, STANDARD_DECK.cards().collect::<Vec<_>>());
+        assert_eq!(deck[..2], [card("AC"), card("2C")]);
+        assert_eq!(deck[12..14], [card("KC"), card("AD")]);
+        assert_eq!(deck[51], card("KS"));
+    }
+
+    #[test]
+    fn sweep_leaves_held_cards() {
+        // 7C covers 6C, so the game cannot be finished by foundation moves
+        let mut fc = board("quick_free_cell/6D------/5C5D5H5S/\
+            8C6C7C/7D/8D/7H6H/8H/7S6S/8S/");
+
+        while fc.sweep_step_except(u32::max_value(), &[card("6D"), card("6H")]) {}
+
+        assert_eq!(fc.to_compact_string(), "quick_free_cell/6D------/5C5D5H7S/\
+            8C6C7C/7D/8D/7H6H/8H//8S/");
+    }
+
+    #[test]
+    fn best_move_safe_foundation() {
+        let fc = board("quick_free_cell/--------/5C5D5H5S/\
+            7H6C/6D7S/6H7C/6S7D/8C/8D/8H/8S");
+
+        assert_eq!(fc.best_move(), Some(Move::TableauToFoundation(0)));
+    }
+
+    #[test]
+    fn best_move_avoids_burying() {
+        // 7S may go on 8H, covering 6C, or on 8D
+        let fc = board("quick_free_cell/--------/5C5D5H5S/\
+            6C8H/8D/7S/6D8C/6H8S/6S7C7D/7H/");
+
+        assert_eq!(fc.best_move(), Some(Move::TableauToTableau(2, 1, 1)));
+    }
+
+    #[test]
+    fn best_move_none_when_won() {
+        let fc = board("quick_free_cell/--------/8C8D8H8S////////");
+
+        assert_eq!(fc.best_move(), None);
+    }
+
+    #[test]
+    fn deal_microsoft_numbering() {
+        let fc = FreeCell::with_seed(Variant::FreeCell, 1);
+
+        assert_eq!(fc.to_compact_string(), "free_cell/--------/--------/\
+            JDKD2S4C3S6D6S/2DKCKS5CTD8S9C/9H9S9DTS4S8D2H/JC5SQDQHTHQS6H/\
+            5DADJS4H8H6C/7HQCASAC2C3D/7CKHAH4DJH8C/5H3H3C7S7DTC");
+    }
+}
@@ -1,20 +1,63 @@
-use std::cmp::{max, min};
-use std::fs::File;
+use std::cmp::{max, min, Reverse};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{remove_file, rename, File};
 use std::io::{self, Read, Write};
-use std::mem::replace;
-use std::path::PathBuf;
-use std::time::Duration;
+use std::mem::{replace, take};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use dirs::config_dir;
-use mortal::{Cursor, Key, Screen, Size, Style};
+use rand::{thread_rng, Rng};
+use mortal::{Color as TermColor, Cursor, Key, Screen, Size, Style};
 use serde::{Deserialize, Serialize};
 use serde_json as json;
 
 use term_game::{Game, GameImpl};
 
-use crate::freecell::{Card, Color, Face, FreeCell, ACE, JACK, QUEEN, KING};
+use crate::freecell::{Card, Color, DealConfig, Event, Face, FreeCell, Move, MoveError,
+    Position, Suit, Variant, ACE, JACK, QUEEN, KING, VARIANTS};
+use crate::solver::{solve, Solution};
+use crate::tutorial::STEPS;
+
+/// Slot keys, in slot order; the first eight are on the home row
+const SLOT_NAMES: [char; 14] = [
+    'A', 'S', 'D', 'F', 'G', 'H', 'J', 'K',
+    'Z', 'X', 'C', 'V', 'B', 'M',
+];
+
+/// Number of slot keys given in `slot_keys`: one for each slot
+/// of the longest row in any variant
+const SLOT_KEY_COUNT: usize = 8;
+
+/// Keys which act as commands during play and cannot be slot keys
+const COMMAND_KEYS: &str = "lnpqrtui#? ";
+
+/// Checks that configured slot keys are distinct printable ASCII characters,
+/// number `SLOT_KEY_COUNT`, and do not act as commands.
+///
+/// Letters are given as they are shown and are typed in lowercase.
+fn validate_slot_keys(keys: &str) -> Result<(), String> {
+    let keys = keys.chars().map(|c| c.to_ascii_lowercase()).collect::<Vec<_>>();
+
+    if keys.len() != SLOT_KEY_COUNT {
+        return Err(format!("slot_keys must give {} keys", SLOT_KEY_COUNT));
+    }
+
+    for (i, &ch) in keys.iter().enumerate() {
+        if keys[..i].contains(&ch) {
+            return Err(format!("slot_keys gives {:?} more than once", ch));
+        }
+        if !ch.is_ascii_graphic() {
+            return Err(format!("slot_keys gives {:?}, which is not printable ASCII", ch));
+        }
+        if COMMAND_KEYS.contains(ch) {
+            return Err(format!("slot_keys gives command key {:?}", ch));
+        }
+    }
 
-const SLOT_NAMES: [char; 8] = ['A', 'S', 'D', 'F', 'G', 'H', 'J', 'K'];
+    Ok(())
+}
 
 const HELP_TEXT: &'static str = "\
 ?             Show this help screen
@@ -22,28 +65,113 @@ Q             Quit the game (requires confirmation)
 N             Start a new game
 P             Pause or unpause the game
 S             Show game stats
+V             Change the game variant (while paused)
+A             Analyze the game for losing moves (while paused)
+T             Start the tutorial (while paused)
+C             Change foundation collection speed (while paused)
+R             Restart the current deal (while paused or after a win)
+H             Show challenge deals (while paused)
+#             Toggle slot key labels
+Shift-C       Toggle next needed rank and cards left per suit
+I             Toggle time, moves, undos, stall, and deal beside the board
 
 L             Start card lookup (Esc or Space to end)
 R or B        Search for a Red or Black card
+C D H S       Search for a card of one suit
 0-9 J Q K A   Search for a card value (0 means 10)
 L again       Search for lowest cards in play
+Shift-L       Search for the next card needed on each foundation
+Enter         Move the card found by suit and value
+
+Shift-R       Resign the game (counts as a loss)
+Shift-H       Show a move which leads to a win
+Shift-A       Make a quick move chosen by simple rules
+Shift-F       Take the last card sent to foundation back to reserve
+Shift-E       Move all remaining cards to foundation,
+              once no other moves are needed to win
+Shift-K       Keep the selected card from going to foundation
+              automatically, or allow it again
 
 Esc or Space  Cancel an action
 U             Undo an action
 Ctrl-R        Redo an action
-A-K           Reference a slot on the tableau
-R, then A-F   Reference a slot on the reserve
+Shift-U       Go to a move number in the undo history
+Shift-D       Show cards moved since a move number, in bold;
+              again to hide them
+A-K           Reference a slot on the tableau (as set by slot_keys)
+Z X C V B M   Reference slots beyond the eighth, if any, unless slot_keys is set
+R, then A-F   Reference a slot on the reserve, by its slot key
 T             Reference the foundation
+Tab, Down     Move focus to the next group of slots
+Up            Move focus to the previous group of slots
+Left, Right   Move focus within a group of slots
+Enter         Reference the focused slot
 
 To move a card, reference the source slot,
   then the destination slot.
-Pressing tableau key twice moves to reserve.
+Pressing tableau key twice moves to reserve,
+  or as set by double_tap.
+To choose a reserve slot, press R, then the slot;
+  press R twice for the first free slot.
 ";
 
 fn one_sec() -> Option<Duration> { Some(Duration::new(1, 0)) }
 
+/// Longest text which may be entered with `read_line`
+const MAX_LINE_INPUT: usize = 64;
+
+/// Number of states the solver may expand for each analyzed position
+const ANALYSIS_BUDGET: usize = 10_000;
+
+/// Number of states the solver may expand to find the par of a won deal
+const PAR_BUDGET: usize = 100_000;
+
+/// Number of states the solver may expand to find a hint
+const HINT_BUDGET: usize = 50_000;
+
+/// Widest space between tableau columns chosen to fit the screen
+const MAX_AUTO_GAP: usize = 3;
+
+/// Number of ticks for which recently moved cards are marked
+const RECENT_TICKS: u32 = 10;
+
+/// Number of ticks for which each position is shown in replay,
+/// from slowest to fastest
+const REPLAY_SPEEDS: [u32; 4] = [16, 8, 4, 1];
+
+/// Number of recent game results shown in stats
+const RECENT_RESULTS: usize = 15;
+
+/// Maximum number of streak freezes that may be held at once
+const MAX_STREAK_FREEZES: u32 = 3;
+
+/// Deal numbers offered as challenges from the pause screen.
+///
+/// Each is a standard FreeCell deal which the solver wins, but only
+/// with a budget of more than 100 000 states and at most 1 000 000.
+/// A challenge counts as solved once a best time is recorded for its deal
+/// in the variant being played.
+const CHALLENGE_DEALS: [u32; 10] = [
+    191, 210, 213, 273, 307, 617, 619, 705, 709, 1941,
+];
+
+/// Named foundation collection speeds, selectable from the pause screen:
+/// the name, `sweep_cards`, and `sweep_ticks` of each
+const SWEEP_PRESETS: [(&str, u32, u32); 4] = [
+    ("Instant", 0, 1),
+    ("Normal", 3, 1),
+    ("Gentle", 1, 1),
+    ("Stepwise", 1, 4),
+];
+
 pub struct FreeCellGame {
+    /// Directory in which config, stats, and saved games are kept
+    data_dir: Option<PathBuf>,
     fc: FreeCell,
+    variant: Variant,
+    /// Seed from which the current game was dealt
+    seed: u32,
+    config: Config,
     stats: Stats,
     undo: Vec<FreeCell>,
     /// Index into `undo` containing the current state;
@@ -51,17 +179,99 @@ pub struct FreeCellGame {
     undo_index: usize,
     action: Option<Action>,
     locate: Option<Locate>,
+    /// Slot chosen with Tab and arrow keys, to be referenced with Enter
+    focus: Option<Focus>,
+    /// Prompt and text being entered with `read_line`
+    line_input: Option<(String, String)>,
+    /// Text entered with `read_line`, or `None` if entry was cancelled
+    line_result: Option<String>,
     pause_draw: Draw,
+    /// Whether a `ConfirmPrompt` is awaiting an answer
     wait_confirm: bool,
-    confirm_result: bool,
     try_sweep: bool,
+    /// Ticks remaining before the next sweep step
+    sweep_wait: u32,
+    /// Cards moved by the last move and the sweeps which followed it
+    recent: Vec<Card>,
+    /// Ticks remaining until `recent` is cleared
+    recent_ticks: u32,
+    /// Notable events of recent moves, not yet announced
+    events: Vec<Event>,
+    /// Move number whose board is compared with the current board,
+    /// and the cards whose places differ between them; kept until a new move
+    diff: Option<(usize, Vec<Card>)>,
+    /// Cards exposed by the last move, which are not swept
+    /// until the grace period has passed
+    held: Vec<Card>,
+    /// Cards which the player has kept from automatic foundation moves
+    locked: Vec<Card>,
+    /// Card most recently placed on the tableau, held back from automatic
+    /// foundation moves until the next move if `keep_placed` is set
+    placed: Option<Card>,
+    /// Time of the move which exposed the `held` cards
+    held_since: Instant,
     game_won: bool,
+    analysis: Option<Analysis>,
+    replay: Option<Replay>,
+    /// Animation shown on the victory screen
+    celebration: Option<Celebration>,
+    /// Number of tableau cards shown, in order dealt, while the deal is animated
+    dealing: Option<usize>,
+    /// Play time carried over from a resumed game
+    time_offset: u32,
+    /// Whether the current game was resumed from a save
+    resumed: bool,
+    /// Whether the save file holds the current game; it is removed
+    /// once the game ends other than by being saved again
+    save_kept: bool,
+    /// Whether the end of the current game has been recorded in stats
+    recorded: bool,
+    /// Number of times a move was undone in the current game
+    undos: u32,
+    /// Number of hints and analyses used in the current game
+    hints: u32,
+    /// Seconds of undo penalty included in `time_offset`
+    penalty: u32,
+    /// Summary of the finished game
+    summary: Option<Summary>,
+    /// Time at which a new game is to be dealt from the summary screen,
+    /// if `auto_deal` is set and no key has been pressed
+    auto_deal_at: Option<Instant>,
+    /// Index of the deal selected on the challenge screen
+    challenge: usize,
+    tutorial: Option<Tutorial>,
+    /// Time of the most recent key event
+    last_key: Instant,
+    /// Whether the game was paused for inactivity;
+    /// if so, any key resumes it
+    idle_paused: bool,
+    /// Message to be shown once the game is running
+    notice: Option<String>,
+    /// Whether a resumed game awaits the startup choice of `on_launch`,
+    /// or a board set up from outside awaits play
+    launch_pending: bool,
+    /// Board set up from outside, to be played once the game is running
+    setup: Option<FreeCell>,
+    /// Whether the current board was set up from outside rather than dealt;
+    /// its result is not recorded in stats
+    custom: bool,
+    /// Whether every remaining card is being moved to foundation
+    finishing: bool,
+    /// Whether finishing has been offered since the game last became
+    /// one that foundation moves alone can win
+    finish_offered: bool,
+    /// Whether moves are being collected into one undo step,
+    /// for which the board has already been saved
+    undo_group: bool,
+    /// Suit of the foundation to which a card was most recently added
+    last_foundation: Option<Suit>,
 }
 
 #[derive(Deserialize)]
 struct StatsFile {
     games: Option<u32>,
     won: Option<u32>,
+    abandoned: Option<u32>,
 
     highest_time: Option<u32>,
     lowest_time: Option<u32>,
@@ -69,12 +279,28 @@ struct StatsFile {
 
     longest_streak: Option<u32>,
     current_streak: Option<u32>,
+    streak_freezes: Option<u32>,
+
+    best_times: Option<BTreeMap<u32, u32>>,
+
+    hint_free_wins: Option<u32>,
+
+    recent: Option<Vec<bool>>,
+
+    par_moves: Option<BTreeMap<u32, u32>>,
+
+    variants: Option<BTreeMap<String, StatsFile>>,
 }
 
+/// Stats for standard FreeCell games, along with stats
+/// for each other variant
 #[derive(Default, Serialize)]
 struct Stats {
     games: u32,
     won: u32,
+    /// Games left unfinished before enough moves were made
+    /// to count as played; these are not counted in `games`
+    abandoned: u32,
 
     highest_time: u32,
     lowest_time: u32,
@@ -82,6 +308,26 @@ struct Stats {
 
     longest_streak: u32,
     current_streak: u32,
+    streak_freezes: u32,
+
+    /// Lowest winning time of each deal, by seed
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    best_times: BTreeMap<u32, u32>,
+
+    /// Games won without hints or analysis
+    hint_free_wins: u32,
+
+    /// Results of the most recent games, oldest first;
+    /// `true` for a win
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    recent: Vec<bool>,
+
+    /// Length of a solver solution of each won deal, by seed
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    par_moves: BTreeMap<u32, u32>,
+
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    variants: BTreeMap<String, Stats>,
 }
 
 impl From<StatsFile> for Stats {
@@ -89,16 +335,70 @@ impl From<StatsFile> for Stats {
         Stats{
             games: s.games.unwrap_or(0),
             won: s.won.unwrap_or(0),
+            abandoned: s.abandoned.unwrap_or(0),
             highest_time: s.highest_time.unwrap_or(0),
             lowest_time: s.lowest_time.unwrap_or(0),
             total_time: s.total_time.unwrap_or(0),
             longest_streak: s.longest_streak.unwrap_or(0),
             current_streak: s.current_streak.unwrap_or(0),
+            streak_freezes: s.streak_freezes.unwrap_or(0),
+            best_times: s.best_times.unwrap_or_default(),
+            hint_free_wins: s.hint_free_wins.unwrap_or(0),
+            recent: s.recent.unwrap_or_default(),
+            par_moves: s.par_moves.unwrap_or_default(),
+            variants: s.variants.map(|v| v.into_iter()
+                .map(|(k, s)| (k, s.into())).collect()).unwrap_or_default(),
         }
     }
 }
 
 impl Stats {
+    /// Returns the stats for games of the given variant.
+    fn variant_mut(&mut self, variant: Variant) -> &mut Stats {
+        if variant == Variant::FreeCell {
+            self
+        } else {
+            self.variants.entry(variant.key().to_owned()).or_default()
+        }
+    }
+
+    /// Resets stats, retaining stats of other variants
+    /// and the par of each deal, which describes the deal rather than play.
+    fn clear(&mut self) {
+        *self = Stats{
+            par_moves: take(&mut self.par_moves),
+            variants: take(&mut self.variants),
+            ..Stats::default()
+        };
+    }
+
+    /// Extends the win streak with a win, earning a streak freeze every
+    /// `freeze_wins` wins if nonzero, or ends it with a loss,
+    /// unless a freeze is spent to keep it.
+    fn update_streak(&mut self, won: bool, freeze_wins: u32) {
+        if won {
+            self.current_streak += 1;
+            self.longest_streak = max(self.current_streak, self.longest_streak);
+
+            if freeze_wins != 0 && self.current_streak % freeze_wins == 0 {
+                self.streak_freezes = min(self.streak_freezes + 1, MAX_STREAK_FREEZES);
+            }
+        } else if freeze_wins != 0 && self.streak_freezes != 0 && self.current_streak != 0 {
+            // Spend a freeze rather than losing the streak
+            self.streak_freezes -= 1;
+        } else {
+            self.current_streak = 0;
+        }
+    }
+
+    /// Records the result of a game among recent results.
+    fn push_result(&mut self, won: bool) {
+        self.recent.push(won);
+
+        let excess = self.recent.len().saturating_sub(RECENT_RESULTS);
+        self.recent.drain(..excess);
+    }
+
     fn win_rate(&self) -> u32 {
         if self.games == 0 {
             0
@@ -116,13 +416,500 @@ impl Stats {
     }
 }
 
-fn stats_path() -> PathBuf {
-    let config = config_dir().expect("cannot find config dir");
-    config.join("mur-freecell/stats.cfg")
+#[derive(Default, Deserialize)]
+struct ConfigFile {
+    variant: Option<Variant>,
+    streak_freeze_wins: Option<u32>,
+    deal_animation: Option<bool>,
+    compact: Option<bool>,
+    show_labels: Option<bool>,
+    show_indices: Option<bool>,
+    status_position: Option<StatusPosition>,
+    status_style: Option<StatusStyle>,
+    idle_pause: Option<u64>,
+    sweep_cards: Option<u32>,
+    sweep_ticks: Option<u32>,
+    min_played_moves: Option<usize>,
+    sweep_grace: Option<u64>,
+    win_animation: Option<bool>,
+    column_gap: Option<usize>,
+    show_suit_counts: Option<bool>,
+    track_hints: Option<bool>,
+    four_color: Option<bool>,
+    on_launch: Option<OnLaunch>,
+    undo_penalty: Option<u32>,
+    keep_source: Option<bool>,
+    highlight_keys: Option<bool>,
+    double_tap: Option<DoubleTap>,
+    show_info: Option<bool>,
+    autoplay: Option<AutoplayMode>,
+    rate_moves: Option<bool>,
+    layout: Option<Layout>,
+    confirm_timeout: Option<u64>,
+    keep_placed: Option<bool>,
+    auto_deal: Option<u64>,
+    suit_glyphs: Option<SuitGlyphs>,
+    persistent_messages: Option<bool>,
+    stall_hint: Option<usize>,
+    handicap: Option<Handicap>,
+    announce_events: Option<bool>,
+    slot_keys: Option<String>,
+    confirm_last_cell: Option<bool>,
+}
+
+struct Config {
+    /// Variant played at startup
+    variant: Variant,
+    /// Number of consecutive wins which earns a streak freeze;
+    /// zero disables streak freezes
+    streak_freeze_wins: u32,
+    /// Whether to animate dealing a new game
+    deal_animation: bool,
+    /// Whether to always draw the field in compact style;
+    /// otherwise, compact style is used only when the screen is too narrow
+    compact: bool,
+    /// Whether to label reserve slots and the foot of the tableau
+    /// with their slot keys
+    show_labels: bool,
+    /// Whether to number card positions within tableau columns
+    show_indices: bool,
+    /// Screen line on which action status is drawn
+    status_position: StatusPosition,
+    /// Style of action status text
+    status_style: StatusStyle,
+    /// Seconds without a key event after which the game is paused;
+    /// zero disables pausing for inactivity
+    idle_pause: u64,
+    /// Number of cards automatically moved to foundation at each step;
+    /// zero moves all cards at once
+    sweep_cards: u32,
+    /// Number of ticks between automatic foundation steps
+    sweep_ticks: u32,
+    /// Number of moves an unfinished game must reach to count as played;
+    /// games abandoned sooner are counted separately
+    min_played_moves: usize,
+    /// Seconds for which a card exposed by a move is held back
+    /// from automatic foundation moves; zero disables the grace period
+    sweep_grace: u64,
+    /// Whether to animate cards cascading across the victory screen
+    win_animation: bool,
+    /// Number of spaces between tableau columns;
+    /// if `None`, as many as fit, up to `MAX_AUTO_GAP`
+    column_gap: Option<usize>,
+    /// Whether to show, for each suit, the next rank needed on foundation
+    /// and the number of cards not yet on foundation
+    show_suit_counts: bool,
+    /// Whether to count hints and analysis used in each game,
+    /// showing the count in game summaries and stats
+    track_hints: bool,
+    /// Whether to draw each suit in its own color,
+    /// rather than red and black
+    four_color: bool,
+    /// Handling of a saved game at startup
+    on_launch: OnLaunch,
+    /// Seconds added to play time for each undo
+    undo_penalty: u32,
+    /// Whether a pending action is kept when a key refers to
+    /// an empty or missing slot; otherwise, the action is cancelled
+    keep_source: bool,
+    /// Whether to embolden the labels of tableau columns
+    /// whose keys would take part in a legal move
+    highlight_keys: bool,
+    /// Effect of pressing a tableau slot key twice
+    double_tap: DoubleTap,
+    /// Whether to show time, moves, undos, and deal of the current game
+    /// beside the board
+    show_info: bool,
+    /// Handling of cards which may safely be moved to foundation
+    autoplay: AutoplayMode,
+    /// Whether to rate the moves of a won game against a solver solution
+    rate_moves: bool,
+    /// Placement of reserve and foundation slots
+    layout: Layout,
+    /// Seconds after which an unanswered confirmation is declined;
+    /// zero waits indefinitely
+    confirm_timeout: u64,
+    /// Whether a card placed on the tableau is held back
+    /// from automatic foundation moves until the next move
+    keep_placed: bool,
+    /// Seconds for which the summary of a finished game is shown
+    /// before a new game is dealt; zero waits for a key
+    auto_deal: u64,
+    /// Characters with which suits are drawn
+    suit_glyphs: SuitGlyphs,
+    /// Whether messages stay on the message line until replaced,
+    /// rather than disappearing after a time
+    persistent_messages: bool,
+    /// Number of moves without foundation progress after which a hint
+    /// is suggested; zero disables the suggestion
+    stall_hint: usize,
+    /// Cards placed on foundation before each deal
+    handicap: Handicap,
+    /// Whether notable events, such as a column becoming empty,
+    /// are announced on the message line
+    announce_events: bool,
+    /// Key label of each slot, in slot order
+    slot_keys: Vec<char>,
+    /// Whether to ask before a move fills the last free reserve slot
+    confirm_last_cell: bool,
+}
+
+impl Config {
+    /// Returns the key label of a slot, or a space if no key refers to it.
+    fn slot_name(&self, pos: usize) -> char {
+        self.slot_keys.get(pos).cloned().unwrap_or(' ')
+    }
+
+    /// Returns the slot referenced by a key.
+    ///
+    /// Capital letters act as commands and never refer to a slot.
+    fn slot_key(&self, ch: char) -> Option<u8> {
+        if ch.is_ascii_uppercase() {
+            return None;
+        }
+
+        let ch = ch.to_ascii_uppercase();
+        self.slot_keys.iter().position(|&c| c == ch).map(|n| n as u8)
+    }
+
+    /// Returns the color in which cards of the given suit are drawn.
+    fn suit_color(&self, suit: Suit) -> Option<TermColor> {
+        if self.four_color {
+            match suit {
+                Suit::Club => Some(TermColor::Green),
+                Suit::Diamond => Some(TermColor::Blue),
+                Suit::Heart => Some(TermColor::Red),
+                Suit::Spade => None,
+            }
+        } else {
+            suit.color().term_color()
+        }
+    }
+
+    /// Returns the character with which the given suit is drawn.
+    fn suit_char(&self, suit: Suit) -> char {
+        match self.suit_glyphs {
+            SuitGlyphs::Unicode => suit.char(),
+            SuitGlyphs::Ascii => suit.letter(),
+        }
+    }
+
+    /// Returns the name of a card in messages, such as `♠10`.
+    fn card_name(&self, card: Card) -> String {
+        format!("{}{}", self.suit_char(card.suit), card.value)
+    }
+
+    /// Returns the name of the collection speed preset in use, if any.
+    fn sweep_preset(&self) -> Option<&'static str> {
+        SWEEP_PRESETS.iter()
+            .find(|&&(_, cards, ticks)| cards == self.sweep_cards && ticks == self.sweep_ticks)
+            .map(|&(name, _, _)| name)
+    }
+
+    /// Selects the collection speed preset following the one in use.
+    fn next_sweep_preset(&mut self) {
+        let pos = SWEEP_PRESETS.iter()
+            .position(|&(_, cards, ticks)| cards == self.sweep_cards && ticks == self.sweep_ticks)
+            .map_or(0, |pos| (pos + 1) % SWEEP_PRESETS.len());
+        let (_, cards, ticks) = SWEEP_PRESETS[pos];
+
+        self.sweep_cards = cards;
+        self.sweep_ticks = ticks;
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        ConfigFile::default().into()
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum StatusPosition {
+    /// Below the title line
+    Top,
+    /// On the bottom line, opposite messages
+    Bottom,
+}
+
+/// Handling of a saved game at startup
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum OnLaunch {
+    /// Resume the saved game
+    Resume,
+    /// Discard the saved game, counting it as unfinished, and deal a new one
+    AlwaysNew,
+    /// Ask whether to resume the saved game
+    Prompt,
+}
+
+/// Placement of reserve and foundation slots
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Layout {
+    /// In a row above the tableau
+    Horizontal,
+    /// In a column beside the tableau
+    Vertical,
+}
+
+/// Characters with which suits are drawn
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SuitGlyphs {
+    /// Suit symbols, such as `♠`
+    Unicode,
+    /// Suit letters, such as `S`, for terminals which lack the symbols
+    /// or draw them two columns wide
+    Ascii,
+}
+
+/// Guesses from the locale whether the terminal can draw suit symbols,
+/// each one column wide.
+///
+/// The symbols are used only with a UTF-8 locale; and not for Chinese,
+/// Japanese, or Korean, in which terminals commonly draw them two columns wide.
+fn detect_suit_glyphs() -> SuitGlyphs {
+    // The first of these which is set determines the character encoding
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"].iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|v| !v.is_empty())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    let utf8 = locale.contains("utf-8") || locale.contains("utf8");
+    let wide = ["zh", "ja", "ko"].iter().any(|lang| locale.starts_with(lang));
+
+    if utf8 && !wide {
+        SuitGlyphs::Unicode
+    } else {
+        SuitGlyphs::Ascii
+    }
+}
+
+/// Handling of cards which may safely be moved to foundation
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum AutoplayMode {
+    /// Move the cards automatically
+    Safe,
+    /// Leave the cards in place, showing how to move one
+    Suggest,
+    /// Leave the cards in place
+    Off,
+}
+
+/// Cards placed on foundation before a deal, to make the game easier
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Handicap {
+    /// Deal every card
+    None,
+    /// Place each ace on foundation
+    AcesHome,
+    /// Place each ace and two on foundation
+    TwosHome,
+}
+
+impl Handicap {
+    /// Returns the cards to place before dealing a game of the given variant.
+    fn deal_config(&self, variant: Variant) -> Option<DealConfig> {
+        let rank = match *self {
+            Handicap::None => return None,
+            Handicap::AcesHome => Face(ACE),
+            Handicap::TwosHome => Face(2),
+        };
+
+        Some(DealConfig::home_to(variant.deck(), rank))
+    }
+}
+
+/// Effect of pressing the key of a selected tableau slot again
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum DoubleTap {
+    /// Move the top card to the first free reserve slot
+    Move,
+    /// Select the top card for reserve, as with `r`,
+    /// to be confirmed with a reserve key
+    SelectReserve,
+    /// Keep the slot selected
+    Off,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum StatusStyle {
+    Plain,
+    Bold,
+    Reverse,
+    Underline,
+}
+
+impl StatusStyle {
+    fn style(&self) -> Style {
+        match *self {
+            StatusStyle::Plain => Style::empty(),
+            StatusStyle::Bold => Style::BOLD,
+            StatusStyle::Reverse => Style::REVERSE,
+            StatusStyle::Underline => Style::UNDERLINE,
+        }
+    }
+}
+
+impl From<ConfigFile> for Config {
+    fn from(c: ConfigFile) -> Config {
+        Config{
+            variant: c.variant.unwrap_or_default(),
+            streak_freeze_wins: c.streak_freeze_wins.unwrap_or(0),
+            deal_animation: c.deal_animation.unwrap_or(true),
+            compact: c.compact.unwrap_or(false),
+            show_labels: c.show_labels.unwrap_or(false),
+            show_indices: c.show_indices.unwrap_or(false),
+            status_position: c.status_position.unwrap_or(StatusPosition::Bottom),
+            status_style: c.status_style.unwrap_or(StatusStyle::Bold),
+            idle_pause: c.idle_pause.unwrap_or(300),
+            sweep_cards: c.sweep_cards.unwrap_or(3),
+            sweep_ticks: max(c.sweep_ticks.unwrap_or(1), 1),
+            min_played_moves: c.min_played_moves.unwrap_or(5),
+            sweep_grace: c.sweep_grace.unwrap_or(0),
+            win_animation: c.win_animation.unwrap_or(true),
+            column_gap: c.column_gap,
+            show_suit_counts: c.show_suit_counts.unwrap_or(false),
+            track_hints: c.track_hints.unwrap_or(true),
+            four_color: c.four_color.unwrap_or(false),
+            on_launch: c.on_launch.unwrap_or(OnLaunch::Prompt),
+            undo_penalty: c.undo_penalty.unwrap_or(0),
+            keep_source: c.keep_source.unwrap_or(true),
+            highlight_keys: c.highlight_keys.unwrap_or(false),
+            double_tap: c.double_tap.unwrap_or(DoubleTap::Move),
+            show_info: c.show_info.unwrap_or(false),
+            autoplay: c.autoplay.unwrap_or(AutoplayMode::Safe),
+            rate_moves: c.rate_moves.unwrap_or(true),
+            layout: c.layout.unwrap_or(Layout::Horizontal),
+            confirm_timeout: c.confirm_timeout.unwrap_or(0),
+            keep_placed: c.keep_placed.unwrap_or(false),
+            auto_deal: c.auto_deal.unwrap_or(0),
+            suit_glyphs: c.suit_glyphs.unwrap_or_else(detect_suit_glyphs),
+            persistent_messages: c.persistent_messages.unwrap_or(false),
+            stall_hint: c.stall_hint.unwrap_or(0),
+            handicap: c.handicap.unwrap_or(Handicap::None),
+            announce_events: c.announce_events.unwrap_or(false),
+            slot_keys: c.slot_keys.map_or_else(|| SLOT_NAMES.to_vec(),
+                |keys| keys.chars().map(|c| c.to_ascii_uppercase()).collect()),
+            confirm_last_cell: c.confirm_last_cell.unwrap_or(false),
+        }
+    }
+}
+
+/// Returns the directory in which config, stats, and saved games are kept
+/// by default; or `None` if there is no config directory.
+pub fn default_data_dir() -> Option<PathBuf> {
+    config_dir().map(|config| config.join("mur-freecell"))
+}
+
+/// Returns the path of a data file within the given data directory;
+/// or `None` if there is no directory in which to keep it.
+fn data_path(dir: Option<&Path>, name: &str) -> Option<PathBuf> {
+    dir.map(|dir| dir.join(name))
+}
+
+fn config_path(dir: Option<&Path>) -> Option<PathBuf> {
+    data_path(dir, "config.cfg")
+}
+
+fn stats_path(dir: Option<&Path>) -> Option<PathBuf> {
+    data_path(dir, "stats.cfg")
+}
+
+#[derive(Deserialize, Serialize)]
+struct SaveFile {
+    fc: FreeCell,
+    #[serde(default)]
+    seed: Option<u32>,
+    undo: Vec<FreeCell>,
+    undo_index: usize,
+    time: u32,
+    #[serde(default)]
+    undos: u32,
+    #[serde(default)]
+    hints: u32,
+    #[serde(default)]
+    penalty: u32,
+}
+
+fn save_path(dir: Option<&Path>) -> Option<PathBuf> {
+    data_path(dir, "save.cfg")
+}
+
+fn no_data_path() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "no config directory")
+}
+
+fn load_config(dir: Option<&Path>) -> io::Result<Config> {
+    let path = match config_path(dir) {
+        Some(path) => path,
+        None => return Ok(Config::default())
+    };
+
+    let mut f = match File::open(&path) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound =>
+            return Ok(Config::default()),
+        Err(e) => return Err(e)
+    };
+
+    let mut buf = String::new();
+
+    f.read_to_string(&mut buf)?;
+
+    if buf.is_empty() {
+        return Ok(Config::default());
+    }
+
+    let cf: ConfigFile = json::from_str(&buf)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+
+    if let Some(ref keys) = cf.slot_keys {
+        validate_slot_keys(keys)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    }
+
+    Ok(cf.into())
+}
+
+/// Writes aggregate stats for each variant, as kept in the given data
+/// directory, to the given path as CSV.
+///
+/// Times are given in seconds.
+pub fn export_stats(data_dir: Option<&Path>, path: &Path) -> io::Result<()> {
+    let mut stats = load_stats(data_dir)?;
+    let mut out = String::new();
+
+    out.push_str("variant,games,won,abandoned,win_rate,hint_free_wins,\
+        lowest_time,highest_time,average_time,longest_streak,current_streak\n");
+
+    for &variant in &VARIANTS {
+        let s = stats.variant_mut(variant);
+
+        out.push_str(&format!("{},{},{},{},{},{},{},{},{},{},{}\n",
+            variant.key(), s.games, s.won, s.abandoned, s.win_rate(),
+            s.hint_free_wins, s.lowest_time, s.highest_time, s.average_time(),
+            s.longest_streak, s.current_streak));
+    }
+
+    File::create(path)?.write_all(out.as_bytes())
 }
 
-fn load_stats() -> io::Result<Stats> {
-    let mut f = match File::open(&stats_path()) {
+fn load_stats(dir: Option<&Path>) -> io::Result<Stats> {
+    let path = match stats_path(dir) {
+        Some(path) => path,
+        None => return Ok(Stats::default())
+    };
+
+    let mut f = match File::open(&path) {
         Ok(f) => f,
         Err(ref e) if e.kind() == io::ErrorKind::NotFound =>
             return Ok(Stats::default()),
@@ -138,13 +925,102 @@ fn load_stats() -> io::Result<Stats> {
     }
 
     let sf: StatsFile = json::from_str(&buf)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
 
     Ok(sf.into())
 }
 
-fn save_stats(stats: &Stats) -> io::Result<()> {
-    let mut f = File::create(&stats_path())?;
+/// Renames a stats file which could not be read, so that it is kept
+/// rather than replaced when stats are next saved.
+/// Returns the new name of the file.
+fn set_aside_stats(dir: Option<&Path>) -> io::Result<PathBuf> {
+    let path = stats_path(dir).ok_or_else(no_data_path)?;
+    let backup = path.with_extension("cfg.bak");
+
+    rename(&path, &backup)?;
+
+    Ok(backup)
+}
+
+fn load_game(dir: Option<&Path>) -> io::Result<Option<SaveFile>> {
+    let path = match save_path(dir) {
+        Some(path) => path,
+        None => return Ok(None)
+    };
+
+    let mut f = match File::open(&path) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound =>
+            return Ok(None),
+        Err(e) => return Err(e)
+    };
+
+    let mut buf = String::new();
+
+    f.read_to_string(&mut buf)?;
+
+    if buf.is_empty() {
+        return Ok(None);
+    }
+
+    let save: SaveFile = json::from_str(&buf)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+
+    check_save(&save)?;
+
+    Ok(Some(save))
+}
+
+/// Checks that a save, which may have been edited by hand,
+/// can be played without upsetting the undo history or the board.
+fn check_save(save: &SaveFile) -> io::Result<()> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    // The newest state is either current or kept at the end of the history
+    if save.undo_index != save.undo.len() && save.undo_index + 2 > save.undo.len() {
+        return Err(invalid(format!("Undo index {} out of range for {} states",
+            save.undo_index, save.undo.len())));
+    }
+
+    for fc in Some(&save.fc).into_iter().chain(&save.undo) {
+        fc.validate().map_err(|e| invalid(e.to_string()))?;
+    }
+
+    Ok(())
+}
+
+fn save_game(dir: Option<&Path>, save: &SaveFile) -> io::Result<()> {
+    let mut f = File::create(&save_path(dir).ok_or_else(no_data_path)?)?;
+    let mut data = json::to_string(save)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+
+    data.push('\n');
+
+    f.write_all(data.as_bytes())?;
+
+    Ok(())
+}
+
+fn remove_save(dir: Option<&Path>) -> io::Result<()> {
+    let path = match save_path(dir) {
+        Some(path) => path,
+        None => return Ok(())
+    };
+
+    match remove_file(&path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        r => r
+    }
+}
+
+fn save_stats(dir: Option<&Path>, stats: &Stats) -> io::Result<()> {
+    // Without a config directory, stats are kept only for the session
+    let path = match stats_path(dir) {
+        Some(path) => path,
+        None => return Ok(())
+    };
+
+    let mut f = File::create(&path)?;
     let mut data = json::to_string(stats)
         .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
 
@@ -161,6 +1037,19 @@ enum Action {
     Reserve,
     ReserveSlot(u8),
     Slot(u8),
+    /// Tableau slot to be moved to a reserve slot
+    SlotToReserve(u8),
+}
+
+/// Reason for the end of a game
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum EndReason {
+    /// The game was won, or abandoned for a new game or to quit
+    Finish,
+    /// The player gave up on the game, or chose to count it as a loss
+    Resign,
+    /// The game was saved to be resumed later
+    Save,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -169,378 +1058,1946 @@ enum Draw {
     Stats,
     Pause,
     Victory,
+    Defeat,
+    Analysis,
+    Challenge,
+    /// Legality of each move from the selected slot, for testing rules
+    Legality,
+    /// Replay of the finished game
+    Replay,
 }
 
-#[derive(Copy, Clone, Debug)]
-struct Locate {
-    color: Option<Color>,
-    what: Match,
+impl Draw {
+    /// Returns whether this is a screen shown at the end of a game.
+    fn game_over(&self) -> bool {
+        match *self {
+            Draw::Victory | Draw::Defeat => true,
+            _ => false
+        }
+    }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-enum Match {
-    Nothing,
-    Low,
-    Value(u8),
+/// Details of a finished game
+struct Summary {
+    time: u32,
+    moves: usize,
+    undos: u32,
+    hints: u32,
+    seed: u32,
+    /// Whether the game beat the previous best time for its deal
+    new_best: bool,
+    /// Current streak, including the finished game
+    streak: u32,
+    /// Length of a solver solution of the deal, if known
+    par: Option<u32>,
+    /// Whether `par` is yet to be found
+    par_pending: bool,
 }
 
-impl FreeCellGame {
-    pub fn new() -> io::Result<FreeCellGame> {
-        let stats = load_stats()?;
+/// Solver analysis of each position reached in a game
+struct Analysis {
+    positions: Vec<FreeCell>,
+    /// Whether each analyzed position can be won, if known
+    results: Vec<Option<bool>>,
+}
 
-        Ok(FreeCellGame {
-            fc: FreeCell::new(),
-            stats: stats,
-            undo: Vec::with_capacity(64),
-            undo_index: 0,
-            action: None,
-            locate: None,
-            pause_draw: Draw::Pause,
-            wait_confirm: false,
-            confirm_result: false,
-            try_sweep: true,
-            game_won: false,
-        })
+/// Playback of the positions of a finished game
+struct Replay {
+    positions: Vec<FreeCell>,
+    /// Index of the position shown
+    pos: usize,
+    /// Index into `REPLAY_SPEEDS`
+    speed: usize,
+    /// Whether positions advance on their own
+    playing: bool,
+    /// Ticks remaining before the next position is shown
+    wait: u32,
+    /// Screen to which the replay returns
+    from: Draw,
+}
+
+impl Replay {
+    /// Index of the last position, which is the number of moves
+    fn last(&self) -> usize {
+        self.positions.len() - 1
     }
 
-    fn confirm(&mut self, game: &mut Game, msg: &str) -> bool {
-        self.wait_confirm = true;
-        game.set_message(&format!("{} (y/n)", msg), None);
-        game.run(self).unwrap();
-        game.clear_message();
+    /// Shows the given position, stopping playback.
+    fn seek(&mut self, pos: usize) {
+        self.pos = min(pos, self.last());
+        self.playing = false;
+    }
 
-        self.wait_confirm = false;
-        self.confirm_result
+    /// Shows the position a tenth part of the way through the game,
+    /// for each of `n` tenths.
+    fn seek_tenths(&mut self, n: usize) {
+        let last = self.last();
+        self.seek(last * n / 10);
     }
 
-    fn confirm_new_game(&mut self, game: &mut Game) {
-        if self.confirm(game, "Start a new game?") {
-            self.new_game(game);
+    /// Advances playback by one tick, returning whether the position changed.
+    fn tick(&mut self) -> bool {
+        if !self.playing {
+            return false;
         }
-    }
 
-    fn confirm_quit(&mut self, game: &mut Game) {
-        if self.confirm(game, "Quit game?") {
-            self.game_end(game);
-            game.quit();
+        if self.wait != 0 {
+            self.wait -= 1;
+            return false;
+        }
+
+        if self.pos < self.last() {
+            self.pos += 1;
         }
+        if self.pos == self.last() {
+            self.playing = false;
+        }
+        self.wait = REPLAY_SPEEDS[self.speed] - 1;
+        true
     }
+}
 
-    fn game_end(&mut self, game: &mut Game) {
-        if !self.undo.is_empty() {
-            self.stats.games += 1;
+impl Analysis {
+    fn done(&self) -> bool {
+        self.results.len() == self.positions.len()
+    }
 
-            if self.game_won {
-                self.stats.won += 1;
+    /// Analyzes the next position.
+    fn step(&mut self) {
+        let fc = &self.positions[self.results.len()];
+        self.results.push(solve(fc, ANALYSIS_BUDGET).is_solvable());
+    }
 
-                let t = game.play_time();
+    /// Returns the numbers of moves after which the game could no longer be won.
+    fn losing_moves(&self) -> Vec<usize> {
+        (1..self.results.len())
+            .filter(|&i| self.results[i - 1] == Some(true) &&
+                self.results[i] == Some(false))
+            .collect()
+    }
 
-                if self.stats.lowest_time == 0 {
-                    self.stats.lowest_time = t;
-                } else {
-                    self.stats.lowest_time = min(t, self.stats.lowest_time);
-                }
-                self.stats.highest_time = max(t, self.stats.highest_time);
-                self.stats.total_time += t;
+    /// Returns the number of positions the solver could not resolve.
+    fn unknown(&self) -> usize {
+        self.results.iter().filter(|r| r.is_none()).count()
+    }
+}
 
-                self.stats.current_streak += 1;
-                self.stats.longest_streak = max(
-                    self.stats.current_streak, self.stats.longest_streak);
-            } else {
-                self.stats.current_streak = 0;
-            }
-
-            self.save_stats(game);
-        }
-    }
+/// Progress through the tutorial, along with the game it interrupted
+struct Tutorial {
+    step: usize,
+    saved: SaveFile,
+}
 
-    fn clear_stats(&mut self, game: &mut Game) {
-        self.stats = Stats::default();
-        self.save_stats(game);
-    }
+/// Number of positions of bouncing cards which remain drawn
+const TRAIL_LEN: usize = 400;
+
+/// Cards bouncing across the victory screen, one at a time,
+/// each leaving a trail behind it
+struct Celebration {
+    cards: Vec<Card>,
+    /// Index into `cards` of the bouncing card
+    next: usize,
+    /// Bouncing card, with its position and velocity
+    current: Option<(Card, (i32, i32), (i32, i32))>,
+    /// Drawn positions of cards, oldest first
+    trail: Vec<(Card, usize, usize)>,
+}
 
-    fn save_stats(&mut self, game: &mut Game) {
-        if let Err(e) = save_stats(&self.stats) {
-            game.set_message(&format!("Failed to save stats: {}", e), None);
+impl Celebration {
+    fn new(fc: &FreeCell) -> Celebration {
+        // Kings first, as they sit atop the foundation
+        let mut cards = fc.foundation_slots().iter()
+            .filter_map(|&f| f)
+            .flat_map(|top| (ACE..=top.value.0).rev()
+                .map(move |v| Card::new(top.suit, Face(v))))
+            .collect::<Vec<_>>();
+
+        cards.sort_by_key(|c| Reverse(c.value));
+
+        Celebration{
+            cards: cards,
+            next: 0,
+            current: None,
+            trail: Vec::with_capacity(TRAIL_LEN),
         }
     }
 
-    fn draw_game(&mut self, game: &mut Game) {
-        self.draw_field(game);
-    }
+    /// Moves the bouncing card within a screen of the given size,
+    /// launching the next card when it leaves the screen.
+    fn step(&mut self, size: Size) {
+        if self.cards.is_empty() {
+            return;
+        }
 
-    fn draw_action(&mut self, game: &mut Game) {
-        let s = self.action_str();
-        self.draw_status(game, &s);
-    }
+        let (card, (x, y), (dx, dy)) = match self.current.take() {
+            Some(cur) => cur,
+            None => {
+                if self.next == self.cards.len() {
+                    self.next = 0;
+                    self.trail.clear();
+                }
 
-    fn draw_locate(&mut self, game: &mut Game) {
-        if let Some(loc) = self.locate {
-            let mut s = "".to_owned();
+                let i = self.next;
+                self.next += 1;
 
-            s.push_str("L");
+                let slot = i % 4;
+                let x = (size.columns / 2) as i32 - 10 + slot as i32 * 5;
+                let speed = 1 + i as i32 % 3;
+                let dx = if i % 2 == 0 { speed } else { -speed };
 
-            match loc.color {
-                Some(Color::Black) => s.push_str(" B"),
-                Some(Color::Red) => s.push_str(" R"),
-                None => s.push_str(" *")
+                (self.cards[i], (x, 2), (dx, -(i as i32 % 3)))
             }
+        };
 
-            match loc.what {
-                Match::Nothing => s.push_str(" ?"),
-                Match::Low => s.push_str(" LO"),
-                Match::Value(n) => {
-                    use std::fmt::Write;
-                    write!(s, " {}", Face(n)).unwrap()
-                }
-            }
+        let floor = size.lines as i32 - 2;
+        let (x, mut y) = (x + dx, y + dy);
+        let mut dy = dy + 1;
 
-            self.draw_status(game, &s);
+        if y >= floor {
+            y = floor;
+            dy = -(dy * 3 / 4);
         }
-    }
 
-    fn highlight_card(&self, card: Card) -> bool {
-        self.locate.map_or(false, |loc| {
-            let match_color = loc.color.map_or(true,
-                |c| card.suit.color() == c);
-            let match_what = match loc.what {
-                Match::Nothing => false,
-                Match::Low => self.fc.can_move_to_foundation(card),
-                Match::Value(n) => card.value.0 == n
-            };
+        if x < 0 || x + 4 > size.columns as i32 {
+            return;
+        }
 
-            match_color && match_what
-        })
+        if self.trail.len() == TRAIL_LEN {
+            self.trail.remove(0);
+        }
+        self.trail.push((card, y.max(0) as usize, x as usize));
+        self.current = Some((card, (x, y), (dx, dy)));
     }
+}
 
-    fn highlight_foundation(&self, top: Card) -> bool {
-        self.locate.map_or(false, |loc| {
-            let match_color = loc.color.map_or(true,
-                |c| top.suit.color() == c);
-            let match_what = match loc.what {
-                Match::Nothing => false,
-                Match::Low => false,
-                Match::Value(n) => n <= top.value.0
-            };
+/// Group of slots among which focus moves with arrow keys
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Zone {
+    Reserve,
+    Foundation,
+    Tableau,
+}
 
-            match_color && match_what
-        })
-    }
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct Focus {
+    zone: Zone,
+    /// Position of the slot within the zone;
+    /// the foundation is referenced as a whole
+    pos: usize,
+}
 
-    fn draw_status(&mut self, game: &mut Game, s: &str) {
-        let screen = game.screen();
-        let Size{lines, columns} = screen.size();
-        let n = s.len();
+#[derive(Copy, Clone, Debug)]
+struct Locate {
+    color: Option<Color>,
+    /// Suit to match, narrowing `color`
+    suit: Option<Suit>,
+    what: Match,
+}
 
-        screen.set_cursor(Cursor{
-            column: columns - n - 1,
-            line: lines - 1,
-        });
-        screen.write_styled(None, None, Style::BOLD, s);
+impl Locate {
+    fn match_suit(&self, card: Card) -> bool {
+        match self.suit {
+            Some(suit) => card.suit == suit,
+            None => self.color.map_or(true, |c| card.suit.color() == c)
+        }
     }
+}
 
-    fn draw_field(&mut self, game: &mut Game) {
-        let screen = game.screen();
-        let Size{columns, ..} = screen.size();
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Match {
+    Nothing,
+    /// The next card needed on foundation of each suit
+    Low,
+    Value(u8),
+}
 
-        let startx = (columns - ((4 * 5 + 5) * 2 + 1)) / 2;
-        //                       |   |   |    |   ` Plus separator
-        //                       |   |   |    ` On each side
-        //                       |   |   ` Plus surrounding [] and key
-        //                       |   ` Five chars wide (including space in between)
-        //                       ` Four cards
+impl FreeCellGame {
+    pub fn new() -> io::Result<FreeCellGame> {
+        FreeCellGame::with_data_dir(default_data_dir())
+    }
 
-        screen.set_cursor(Cursor{
-            line: 2,
-            column: startx,
-        });
+    /// Creates a game which keeps config, stats, and saved games
+    /// in the given directory; or, if `None`, only for the session.
+    pub fn with_data_dir(data_dir: Option<PathBuf>) -> io::Result<FreeCellGame> {
+        let config = load_config(data_dir.as_deref())?;
+
+        // A corrupt stats file must not keep the game from starting
+        let (stats, stats_error) = match load_stats(data_dir.as_deref()) {
+            Ok(stats) => (stats, None),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData =>
+                (Stats::default(), Some(e)),
+            Err(e) => return Err(e)
+        };
 
-        screen.write_str("R [ ");
+        let seed = thread_rng().gen();
 
-        for r in self.fc.reserve_slots() {
-            match *r {
-                Some(c) => draw_card(screen, c, self.highlight_card(c)),
-                None => screen.write_str("____")
-            }
-            screen.write_str(" ");
+        let mut fcg = FreeCellGame {
+            data_dir: data_dir,
+            fc: FreeCell::with_seed(config.variant, seed),
+            variant: config.variant,
+            seed: seed,
+            config: config,
+            stats: stats,
+            undo: Vec::with_capacity(64),
+            undo_index: 0,
+            action: None,
+            locate: None,
+            focus: None,
+            line_input: None,
+            line_result: None,
+            pause_draw: Draw::Pause,
+            wait_confirm: false,
+            try_sweep: true,
+            sweep_wait: 0,
+            recent: Vec::new(),
+            recent_ticks: 0,
+            events: Vec::new(),
+            diff: None,
+            held: Vec::new(),
+            locked: Vec::new(),
+            placed: None,
+            held_since: Instant::now(),
+            game_won: false,
+            analysis: None,
+            replay: None,
+            celebration: None,
+            dealing: None,
+            time_offset: 0,
+            resumed: false,
+            save_kept: false,
+            recorded: false,
+            undos: 0,
+            hints: 0,
+            penalty: 0,
+            summary: None,
+            auto_deal_at: None,
+            challenge: 0,
+            tutorial: None,
+            last_key: Instant::now(),
+            idle_paused: false,
+            notice: None,
+            launch_pending: false,
+            setup: None,
+            custom: false,
+            finishing: false,
+            finish_offered: false,
+            undo_group: false,
+            last_foundation: None,
+        };
+
+        if fcg.data_dir.is_none() {
+            fcg.push_notice("No config directory found; \
+                stats will not be kept after this session".to_owned());
         }
 
-        screen.write_str("] [ ");
+        if let Some(e) = stats_error {
+            let msg = match set_aside_stats(fcg.data_dir.as_deref()) {
+                Ok(backup) => format!("Stats file is corrupt and was moved to {}: {}",
+                    backup.display(), e),
+                Err(_) => format!("Stats file is corrupt and will be replaced: {}", e),
+            };
+            fcg.push_notice(msg);
+        }
 
-        for f in self.fc.foundation_slots() {
-            match *f {
-                Some(c) => draw_card(screen, c, self.highlight_foundation(c)),
-                None => screen.write_str("____")
+        let save = match load_game(fcg.data_dir.as_deref()) {
+            Ok(save) => save,
+            Err(ref e) if e.kind() == io::ErrorKind::InvalidData => {
+                fcg.push_notice(format!("Saved game is corrupt and was not loaded: {}", e));
+                None
             }
-            screen.write_str(" ");
+            Err(e) => return Err(e)
+        };
+
+        if let Some(save) = save {
+            fcg.variant = save.fc.variant();
+            fcg.fc = save.fc;
+            // A game saved before seeds were recorded has a best time of its own
+            fcg.seed = save.seed.unwrap_or(seed);
+            fcg.undo = save.undo;
+            fcg.undo_index = save.undo_index;
+            fcg.time_offset = save.time;
+            fcg.undos = save.undos;
+            fcg.hints = save.hints;
+            fcg.penalty = save.penalty;
+            fcg.resumed = true;
+            fcg.save_kept = true;
+            fcg.launch_pending = fcg.config.on_launch != OnLaunch::Resume;
+        } else {
+            fcg.begin_deal();
         }
 
-        screen.write_str("] T");
+        Ok(fcg)
+    }
 
-        let startx = (columns - (8 * 6)) / 2;
-        //                       |   ` Six chars wide (including two spaces between)
-        //                       ` Eight slots
+    /// Creates a game, as `with_data_dir`, which starts from the given board
+    /// rather than a new deal.
+    ///
+    /// The board is read as a compact string, as written by
+    /// `FreeCell::to_compact_string`, or otherwise in the layout used
+    /// by fc-solve, for the configured variant. If it is not valid,
+    /// a message is shown and play begins as usual.
+    pub fn with_board(data_dir: Option<PathBuf>, board: &str) -> io::Result<FreeCellGame> {
+        let mut fcg = FreeCellGame::with_data_dir(data_dir)?;
+
+        let res = if board.trim().contains('/') {
+            FreeCell::from_compact_string(board)
+        } else {
+            FreeCell::from_fc_solve_str(fcg.config.variant, board)
+        };
 
-        screen.set_cursor(Cursor{
-            column: startx,
-            line: 4,
-        });
-        screen.write_styled(None, None, Style::UNDERLINE,
-            " A     S     D     F     G     H     J     K  ");
+        match res {
+            Ok(fc) => {
+                fcg.setup = Some(fc);
+                fcg.launch_pending = true;
+            }
+            Err(e) => fcg.push_notice(format!("Board was not loaded: {}", e))
+        }
 
-        let max = self.fc.tableau_slots().iter().map(|t| t.len()).max().unwrap();
-        let mut cols = self.fc.tableau_slots().iter()
-            .map(|t| t.iter()).collect::<Vec<_>>();
+        Ok(fcg)
+    }
 
-        for i in 0..max {
-            screen.set_cursor(Cursor{
-                column: startx,
-                line: i + 5,
-            });
+    /// Adds a message to be shown once the game is running,
+    /// after any added before it.
+    fn push_notice(&mut self, msg: String) {
+        self.notice = Some(match self.notice.take() {
+            Some(prev) => format!("{}  {}", prev, msg),
+            None => msg
+        });
+    }
 
-            for t in &mut cols {
-                match t.next() {
-                    Some(&c) => draw_card(screen, c, self.highlight_card(c)),
-                    None => screen.write_str("    ")
-                }
-                screen.write_str("  ");
-            }
+    /// Plays a board set up from outside, if any;
+    /// otherwise, applies the `on_launch` choice to a resumed game.
+    ///
+    /// A game which is not resumed ends as it would on starting a new game.
+    fn launch(&mut self, game: &mut Game) {
+        if let Some(fc) = self.setup.take() {
+            self.play_board(game, fc);
+            return;
         }
-    }
 
-    fn draw_pause(&mut self, game: &mut Game) {
-        match self.pause_draw {
-            Draw::Pause => {
-                let screen = game.screen();
-                let Size{lines, columns} = screen.size();
-                let mid = lines / 2;
-                let center = columns / 2;
-                let col = center.saturating_sub(3);
+        let resume = match self.config.on_launch {
+            OnLaunch::Resume => true,
+            OnLaunch::AlwaysNew => false,
+            OnLaunch::Prompt => self.confirm(game, "Resume previous game?"),
+        };
 
-                screen.write_at((mid, col), "Paused");
-            }
-            Draw::Help => self.draw_help(game),
-            Draw::Stats => self.draw_stats(game),
-            Draw::Victory => self.draw_victory(game),
+        if !resume {
+            self.new_game(game);
         }
+        game.redraw();
     }
 
-    fn draw_help(&mut self, game: &mut Game) {
-        let screen = game.screen();
-        let Size{lines, columns} = screen.size();
+    fn confirm(&mut self, game: &mut Game, msg: &str) -> bool {
+        self.wait_confirm = true;
+        game.set_message(&format!("{} (y/n)", msg), None);
 
-        let n_lines = HELP_TEXT.lines().count();
-        let max_w = HELP_TEXT.lines().map(|l| l.len()).max().unwrap();
+        let mut prompt = ConfirmPrompt::new(self);
+        game.run(&mut prompt).unwrap();
+        let result = prompt.result;
 
-        screen.set_cursor(Cursor{
-            line: lines.saturating_sub(n_lines).saturating_sub(2) / 2,
-            column: columns.saturating_sub(4) / 2,
-        });
-        screen.write_styled(None, None, Style::BOLD, "HELP");
+        game.clear_message();
+        self.wait_confirm = false;
+        result
+    }
 
-        let startx = columns.saturating_sub(max_w) / 2;
+    /// Reads a line of text, shown after the given prompt on the message line.
+    ///
+    /// Enter accepts the text and Escape cancels entry, returning `None`.
+    fn read_line(&mut self, game: &mut Game, prompt: &str) -> Option<String> {
+        self.line_input = Some((prompt.to_owned(), String::new()));
+        self.line_result = None;
+        game.set_message(prompt, None);
+        game.run(self).unwrap();
+        game.clear_message();
 
-        // Skip a full line
-        screen.next_line(startx);
+        self.line_input = None;
+        self.line_result.take()
+    }
 
-        for line in HELP_TEXT.lines() {
-            screen.next_line(startx);
-            screen.write_str(line);
+    /// Handles a key while reading a line of text.
+    fn line_input_key(&mut self, game: &mut Game, key: Key) {
+        let (prompt, text) = match self.line_input {
+            Some(ref mut input) => (&input.0, &mut input.1),
+            None => return
+        };
+
+        match key {
+            Key::Enter => {
+                self.line_result = Some(text.clone());
+                game.quit();
+                return;
+            }
+            Key::Escape => {
+                game.quit();
+                return;
+            }
+            Key::Backspace => { text.pop(); }
+            Key::Char(ch) if !ch.is_control() && text.len() < MAX_LINE_INPUT =>
+                text.push(ch),
+            _ => return
         }
-    }
 
-    fn draw_stats(&mut self, game: &mut Game) {
-        let screen = game.screen();
-        let Size{lines, columns} = screen.size();
-        let n_lines = 7;
+        game.set_message(&format!("{}{}", prompt, text), None);
+    }
 
-        let startx = columns.saturating_sub(20) / 2;
-        let starty = lines.saturating_sub(n_lines) / 2 - 3;
+    /// Asks for a move number and goes to it in the undo history.
+    fn prompt_jump(&mut self, game: &mut Game) {
+        let prompt = format!("Go to move (0-{}): ", self.last_move());
 
-        screen.set_cursor(Cursor{
-            column: columns.saturating_sub(5) / 2,
-            line: starty,
-        });
-        screen.write_styled(None, None, Style::BOLD, "STATS");
+        if let Some(input) = self.read_line(game, &prompt) {
+            match input.trim().parse() {
+                Ok(n) => self.jump_to(game, n),
+                Err(_) => self.show_message(game, "Enter a move number", one_sec())
+            }
+        }
+    }
 
-        // Skip a full line
-        screen.next_line(startx);
+    /// Reads a move number with whose board to compare the current board,
+    /// or ends a comparison already shown.
+    fn prompt_diff(&mut self, game: &mut Game) {
+        if self.diff.take().is_some() {
+            return;
+        }
 
-        screen.next_line(startx);
-        screen.write_str(&format!("Games played:   {:>5}", self.stats.games));
-        screen.next_line(startx);
-        screen.write_str(&format!("Games won:      {:>5}", self.stats.won));
-        screen.next_line(startx);
-        screen.write_str(&format!("Win rate:       {:>4}%", self.stats.win_rate()));
+        let last = self.last_move();
+        let prompt = format!("Compare with move (0-{}): ", last);
+
+        if let Some(input) = self.read_line(game, &prompt) {
+            match input.trim().parse() {
+                Ok(n) if n <= last => {
+                    self.diff = Some((n, Vec::new()));
+                    self.update_diff();
+                    self.show_message(game,
+                        &format!("Cards moved since move {} are shown in bold", n),
+                        Some(Duration::from_secs(3)));
+                }
+                Ok(_) => self.show_message(game,
+                    &format!("No move {}; last move is {}", input.trim(), last), one_sec()),
+                Err(_) => self.show_message(game, "Enter a move number", one_sec())
+            }
+        }
+    }
 
-        // Skip a line
-        screen.next_line(startx);
+    /// Finds again the cards whose places differ from the compared board,
+    /// after going to another state in the undo history.
+    fn update_diff(&mut self) {
+        if let Some((n, _)) = self.diff {
+            let old = card_places(self.board_at(n));
+            let moved = card_places(&self.fc).into_iter()
+                .filter(|place| !old.contains(place))
+                .map(|(card, _)| card)
+                .collect();
+
+            self.diff = Some((n, moved));
+        }
+    }
 
-        screen.next_line(startx);
-        screen.write_str(&format!("Longest streak: {:>5}", self.stats.longest_streak));
-        screen.next_line(startx);
-        screen.write_str(&format!("Current streak: {:>5}", self.stats.current_streak));
+    /// Returns the board after the given number of moves in the undo history,
+    /// which must be at most `last_move`.
+    fn board_at(&self, n: usize) -> &FreeCell {
+        if n < self.undo.len() { &self.undo[n] } else { &self.fc }
+    }
 
-        // Skip a line
-        screen.next_line(startx);
+    /// Returns the number of moves made since a card was last moved
+    /// to foundation, counting back from the current board.
+    fn stall_moves(&self) -> usize {
+        let pos = min(self.undo_index, self.last_move());
+        let mut total = self.board_at(pos).foundation_total();
 
-        screen.next_line(startx);
-        screen.write_str(&format!("Average time:   {:>5}",
-            time_str(self.stats.average_time())));
-        screen.next_line(startx);
-        screen.write_str(&format!("Lowest time:    {:>5}",
-            time_str(self.stats.lowest_time)));
-        screen.next_line(startx);
-        screen.write_str(&format!("Highest time:   {:>5}",
-            time_str(self.stats.highest_time)));
+        for n in (0..pos).rev() {
+            let prev = self.board_at(n).foundation_total();
 
-        // Skip a line
-        screen.next_line(startx);
+            if prev < total {
+                return pos - n - 1;
+            }
+            total = prev;
+        }
 
-        screen.next_line(startx);
-        screen.write_str("Press 'c' to clear");
+        pos
     }
 
-    fn draw_victory(&mut self, game: &mut Game) {
-        let screen = game.screen();
-        let Size{lines, columns} = screen.size();
+    /// Suggests a hint once the configured number of moves
+    /// has passed without foundation progress.
+    fn check_stall(&self, game: &mut Game) {
+        let limit = self.config.stall_hint;
 
-        screen.set_cursor(Cursor{
-            column: (columns / 2).saturating_sub(4),
-            line: lines / 2,
-        });
-        screen.write_styled(None, None, Style::BOLD, "You won!");
+        if limit != 0 && self.tutorial.is_none() && self.stall_moves() == limit {
+            self.show_message(game, &format!("No cards to foundation in {} moves; \
+                Shift-H shows a hint", limit), Some(Duration::from_secs(3)));
+        }
     }
 
-    fn action(&mut self, game: &mut Game, action: Action) {
-        use self::Action::*;
-
-        game.redraw();
+    /// Returns whether the card has moved since the compared board.
+    fn in_diff(&self, card: Card) -> bool {
+        self.diff.as_ref().map_or(false, |&(_, ref cards)| cards.contains(&card))
+    }
 
-        let old = match self.action.take() {
-            Some(act) => act,
-            None => {
-                match action {
-                    Foundation => game.set_message("Invalid action", one_sec()),
-                    Slot(n) if self.fc.tableau(n as usize).is_empty() => {
-                        game.set_message("Tableau slot is empty", one_sec());
-                    }
-                    _ => self.action = Some(action)
-                }
+    fn confirm_new_game(&mut self, game: &mut Game) {
+        if self.confirm(game, "Start a new game?") {
+            if self.resumed && self.in_progress() &&
+                    !self.confirm(game, "Discard your saved game?") {
                 return;
             }
-        };
+            self.new_game(game);
+        }
+    }
+
+    fn confirm_quit(&mut self, game: &mut Game) {
+        if self.confirm(game, "Quit game?") {
+            // Without a config directory, there is nowhere to save the game
+            if !self.in_progress() || self.data_dir.is_none() {
+                self.game_end(game, EndReason::Finish);
+            } else if self.confirm(game,
+                    "Count unfinished game as a loss? Otherwise, it is saved.") {
+                self.game_end(game, EndReason::Resign);
+            } else {
+                if let Err(e) = self.save_game(game) {
+                    game.set_message(&format!("Failed to save game: {}", e), None);
+                    return;
+                }
+                self.game_end(game, EndReason::Save);
+            }
+            game.quit();
+        }
+    }
+
+    fn confirm_resign(&mut self, game: &mut Game) {
+        if !self.in_progress() {
+            self.show_message(game, "No game in progress", one_sec());
+        } else if self.confirm(game, "Resign this game?") {
+            self.game_lost(game);
+        }
+    }
+
+    /// Returns whether a move has been made in an unfinished game.
+    fn in_progress(&self) -> bool {
+        !self.undo.is_empty() && !self.game_won && !self.recorded
+    }
+
+    /// Returns the play time of the current game,
+    /// including any time carried over from a resumed game.
+    fn play_time(&self, game: &Game) -> u32 {
+        game.play_time() + self.time_offset
+    }
+
+    /// Returns whether the configured time has passed without a key event.
+    fn idle(&self) -> bool {
+        self.config.idle_pause != 0 && !self.wait_confirm && self.line_input.is_none() &&
+            self.last_key.elapsed() >= Duration::from_secs(self.config.idle_pause)
+    }
+
+    fn save_game(&mut self, game: &mut Game) -> io::Result<()> {
+        save_game(self.data_dir.as_deref(), &SaveFile{
+            fc: self.fc.clone(),
+            seed: Some(self.seed),
+            undo: self.undo.clone(),
+            undo_index: self.undo_index,
+            time: self.play_time(game),
+            undos: self.undos,
+            hints: self.hints,
+            penalty: self.penalty,
+        })
+    }
+
+    fn game_end(&mut self, game: &mut Game, reason: EndReason) {
+        if reason != EndReason::Save {
+            self.discard_save(game);
+        }
+
+        if reason == EndReason::Save || self.recorded {
+            // A saved game is not yet won or lost
+            return;
+        }
+
+        self.recorded = true;
+
+        if self.custom {
+            // A board set up from outside is no deal to keep stats for
+            return;
+        }
+
+        if !self.undo.is_empty() {
+            let t = self.play_time(game);
+            let freeze_wins = self.config.streak_freeze_wins;
+            let abandoned = self.abandoned(reason);
+            let stats = self.stats.variant_mut(self.variant);
+
+            if abandoned {
+                stats.abandoned += 1;
+                self.save_stats(game);
+                return;
+            }
+
+            stats.games += 1;
+            stats.push_result(self.game_won && reason == EndReason::Finish);
+
+            if self.game_won && reason == EndReason::Finish {
+                stats.won += 1;
+
+                if stats.lowest_time == 0 {
+                    stats.lowest_time = t;
+                } else {
+                    stats.lowest_time = min(t, stats.lowest_time);
+                }
+                stats.highest_time = max(t, stats.highest_time);
+                stats.total_time += t;
+
+                let best = stats.best_times.entry(self.seed).or_insert(t);
+                *best = min(*best, t);
+
+                if self.hints == 0 {
+                    stats.hint_free_wins += 1;
+                }
+            }
+
+            stats.update_streak(self.game_won && reason == EndReason::Finish, freeze_wins);
+            self.save_stats(game);
+        }
+    }
+
+    /// Removes the save file of a resumed game which has ended.
+    fn discard_save(&mut self, game: &mut Game) {
+        if take(&mut self.save_kept) {
+            if let Err(e) = remove_save(self.data_dir.as_deref()) {
+                game.set_message(&format!("Failed to remove saved game: {}", e), None);
+            }
+        }
+    }
+
+    /// Returns whether a game ending for the given reason was left
+    /// too early to count as played.
+    ///
+    /// This applies only to games left for a new deal or on quitting,
+    /// not to those the player chose to count as lost.
+    fn abandoned(&self, reason: EndReason) -> bool {
+        !self.game_won && reason == EndReason::Finish &&
+            self.last_move() < self.config.min_played_moves
+    }
+
+    fn clear_stats(&mut self, game: &mut Game) {
+        self.stats.variant_mut(self.variant).clear();
+        self.save_stats(game);
+    }
+
+    fn save_stats(&mut self, game: &mut Game) {
+        if let Err(e) = save_stats(self.data_dir.as_deref(), &self.stats) {
+            game.set_message(&format!("Failed to save stats: {}", e), None);
+        }
+    }
+
+    fn draw_game(&mut self, game: &mut Game) {
+        self.draw_field(game);
+
+        if self.penalty != 0 {
+            game.screen().write_at((1, 1), &format!("+{} undo penalty",
+                time_str(self.penalty).trim()));
+        }
+
+        if self.config.show_info {
+            self.draw_info(game);
+        }
+
+        if self.tutorial.is_some() {
+            self.draw_tutorial(game);
+        }
+    }
+
+    /// Draws details of the current game in the top right corner,
+    /// if there is room beside the board.
+    fn draw_info(&self, game: &mut Game) {
+        let lines = [
+            ("Time", time_str(self.play_time(game))),
+            ("Moves", self.last_move().to_string()),
+            ("Undos", self.undos.to_string()),
+            ("Stall", self.stall_moves().to_string()),
+            ("Deal", self.seed.to_string()),
+        ];
+
+        let screen = game.screen();
+        let Size{columns, ..} = screen.size();
+
+        let compact = self.compact_field(columns);
+        let card_w = card_width(compact);
+        let col_w = card_w + self.column_gap(columns, card_w);
+        let field_w = self.field_width(card_w, col_w);
+        let width = 6 + lines.iter().map(|&(_, ref v)| v.len()).max().unwrap_or(0);
+        let startx = columns.saturating_sub(width + 1);
+
+        if startx < (columns + field_w) / 2 + 2 {
+            return;
+        }
+
+        for (i, &(label, ref value)) in lines.iter().enumerate() {
+            screen.write_at((1 + i, startx),
+                &format!("{:<6}{:>w$}", label, value, w = width - 6));
+        }
+    }
+
+    fn draw_tutorial(&mut self, game: &mut Game) {
+        let step = match self.tutorial {
+            Some(ref t) => t.step,
+            None => return
+        };
+
+        let screen = game.screen();
+        let Size{lines, columns} = screen.size();
+
+        let instructions = STEPS[step].instructions(|n| self.config.slot_name(n));
+        let mut text = wrap_text(&instructions, min(columns.saturating_sub(4), 60));
+
+        text.push(String::new());
+        text.push(format!("Step {} of {} (N to skip, Q to exit)",
+            step + 1, STEPS.len()));
+
+        let max_w = text.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+        let startx = columns.saturating_sub(max_w) / 2;
+        let starty = lines.saturating_sub(text.len() + 2);
+
+        for (i, line) in text.iter().enumerate() {
+            screen.write_at((starty + i, startx), line);
+        }
+    }
+
+    fn draw_action(&mut self, game: &mut Game) {
+        let s = self.action_str();
+
+        if let Some((card, n, from)) = self.pending_move() {
+            let columns = game.screen().size().columns;
+
+            let card_s = self.config.card_name(card);
+            let more = if n > 1 {
+                format!(" and {} more", n - 1)
+            } else {
+                String::new()
+            };
+            let to = match self.action {
+                Some(Action::SlotToReserve(_)) => "reserve ?",
+                _ => "?"
+            };
+            // Size of the ordered group at the foot of a selected column,
+            // against the most cards that may be moved together
+            let group = match self.action {
+                Some(Action::Slot(n)) if self.fc.group_size(n as usize) > 1 =>
+                    format!(" (group of {}, room for {})",
+                        self.fc.group_size(n as usize), self.fc.max_group_to_nonempty()),
+                _ => String::new()
+            };
+            let rest = format!("{} from {} \u{2192} {}{}", more, from, to, group);
+
+            let width = "Move ".len() + card_s.chars().count() + rest.chars().count();
+
+            // Fall back to the compact code on narrow terminals
+            if width * 2 <= columns {
+                self.draw_status_parts(game, &[
+                    (None, "Move "),
+                    (self.config.suit_color(card.suit), &card_s),
+                    (None, &rest),
+                ]);
+                return;
+            }
+        }
+
+        self.draw_status(game, &s);
+    }
+
+    fn draw_locate(&mut self, game: &mut Game) {
+        if let Some(loc) = self.locate {
+            let mut s = "".to_owned();
+
+            s.push_str("L");
+
+            match (loc.suit, loc.color) {
+                (Some(suit), _) => {
+                    s.push(' ');
+                    s.push(self.config.suit_char(suit));
+                }
+                (None, Some(Color::Black)) => s.push_str(" B"),
+                (None, Some(Color::Red)) => s.push_str(" R"),
+                (None, None) => s.push_str(" *")
+            }
+
+            match loc.what {
+                Match::Nothing => s.push_str(" ?"),
+                Match::Low => s.push_str(" next needed"),
+                Match::Value(n) => {
+                    use std::fmt::Write;
+                    write!(s, " {}", Face(n)).unwrap()
+                }
+            }
+
+            self.draw_status(game, &s);
+        }
+    }
+
+    /// Returns the style in which to draw a card in the reserve or tableau.
+    fn card_style(&self, card: Card) -> Style {
+        let mut sty = Style::empty();
+
+        if self.highlight_card(card) {
+            sty |= Style::REVERSE;
+        }
+        if self.recent.contains(&card) {
+            sty |= Style::UNDERLINE;
+        }
+        if self.in_diff(card) {
+            sty |= Style::BOLD;
+        }
+
+        sty
+    }
+
+    /// Returns the style in which to draw the top card of a foundation slot.
+    fn foundation_style(&self, top: Card) -> Style {
+        let mut sty = Style::empty();
+
+        if self.highlight_foundation(top) {
+            sty |= Style::REVERSE;
+        }
+        if self.recent.contains(&top) {
+            sty |= Style::UNDERLINE;
+        }
+        if self.in_diff(top) {
+            sty |= Style::BOLD;
+        }
+
+        sty
+    }
+
+    fn highlight_card(&self, card: Card) -> bool {
+        self.locate.map_or(false, |loc| {
+            let match_color = loc.match_suit(card);
+            let match_what = match loc.what {
+                Match::Nothing => false,
+                Match::Low => self.fc.can_move_to_foundation(card),
+                Match::Value(n) => card.value.0 == n
+            };
+
+            match_color && match_what
+        })
+    }
+
+    fn highlight_foundation(&self, top: Card) -> bool {
+        self.locate.map_or(false, |loc| {
+            let match_color = loc.match_suit(top);
+            let match_what = match loc.what {
+                Match::Nothing => false,
+                Match::Low => false,
+                Match::Value(n) => n <= top.value.0
+            };
+
+            match_color && match_what
+        })
+    }
+
+    /// Shows a message on the message line for the given time,
+    /// or until replaced if messages are configured to persist.
+    ///
+    /// A persistent message drawn on the line of action status is clipped
+    /// to the left half of the line, which is left to messages.
+    fn show_message(&self, game: &mut Game, msg: &str, time: Option<Duration>) {
+        if time.is_none() || !self.config.persistent_messages {
+            game.set_message(msg, time);
+            return;
+        }
+
+        let avail = match self.config.status_position {
+            StatusPosition::Top => usize::max_value(),
+            StatusPosition::Bottom => (game.screen().size().columns / 2).saturating_sub(1),
+        };
+
+        if msg.chars().count() > avail {
+            let mut clipped = msg.chars().take(avail.saturating_sub(1)).collect::<String>();
+            clipped.push('\u{2026}');
+            game.set_message(&clipped, None);
+        } else {
+            game.set_message(msg, None);
+        }
+    }
+
+    fn draw_status(&mut self, game: &mut Game, s: &str) {
+        self.draw_status_parts(game, &[(None, s)]);
+    }
+
+    fn draw_status_parts(&mut self, game: &mut Game, parts: &[(Option<TermColor>, &str)]) {
+        let screen = game.screen();
+        let Size{lines, columns} = screen.size();
+
+        let (line, avail) = match self.config.status_position {
+            StatusPosition::Top => (1, columns.saturating_sub(2)),
+            // Leave the left half of the line to messages
+            StatusPosition::Bottom => (lines - 1, (columns / 2).saturating_sub(1)),
+        };
+
+        let n = parts.iter().map(|&(_, s)| s.chars().count()).sum::<usize>();
+
+        // Clip text which does not fit, marking it with an ellipsis
+        let (n, mut left) = if n > avail {
+            (avail, avail.saturating_sub(1))
+        } else {
+            (n, n)
+        };
+
+        screen.set_cursor(Cursor{
+            column: columns.saturating_sub(n + 1),
+            line: line,
+        });
+
+        let style = self.config.status_style.style();
+
+        for &(fg, s) in parts {
+            let len = s.chars().count();
+
+            if len <= left {
+                screen.write_styled(fg, None, style, s);
+                left -= len;
+            } else {
+                let s = s.chars().take(left).collect::<String>();
+                screen.write_styled(fg, None, style, &s);
+                screen.write_styled(None, None, style, "\u{2026}");
+                break;
+            }
+        }
+    }
+
+    /// Returns whether the field should be drawn in compact style
+    /// on a screen of the given width.
+    fn compact_field(&self, columns: usize) -> bool {
+        let card_w = card_width(false);
+        let width = self.field_width(card_w, card_w + 1);
+
+        self.config.compact || columns < width
+    }
+
+    /// Returns the number of spaces between tableau columns
+    /// of cards `card_w` wide on a screen of the given width.
+    fn column_gap(&self, columns: usize, card_w: usize) -> usize {
+        let n_slots = max(self.fc.tableau_count(), 1);
+        let avail = columns.saturating_sub(self.side_width(card_w));
+        let fit = max((avail / n_slots).saturating_sub(card_w), 1);
+
+        match self.config.column_gap {
+            Some(gap) => max(min(gap, fit), 1),
+            None => min(fit, MAX_AUTO_GAP)
+        }
+    }
+
+    /// Returns the width taken beside the tableau by reserve and foundation
+    /// slots of cards `card_w` wide; zero unless they are drawn in a column.
+    fn side_width(&self, card_w: usize) -> usize {
+        match self.config.layout {
+            Layout::Horizontal => 0,
+            // Separator, space, slot key, and space before each card
+            Layout::Vertical => card_w + 4,
+        }
+    }
+
+    /// Returns the width of the board, drawn with cards `card_w` wide
+    /// and tableau columns `col_w` wide.
+    fn field_width(&self, card_w: usize, col_w: usize) -> usize {
+        let n_cards = self.fc.reserve_count() + self.fc.foundation_count();
+        let tableau_w = self.fc.tableau_count() * col_w;
+
+        match self.config.layout {
+            Layout::Horizontal => max(n_cards * (card_w + 1) + 11, tableau_w),
+            Layout::Vertical => tableau_w + self.side_width(card_w),
+        }
+    }
+
+    /// Draws reserve and foundation slots in a row above the tableau,
+    /// centered on a screen of the given width.
+    fn draw_row(&self, screen: &mut Screen, columns: usize, compact: bool) {
+        let card_w = card_width(compact);
+        let blank = "_".repeat(card_w);
+
+        let n_cards = self.fc.reserve_count() + self.fc.foundation_count();
+
+        let startx = columns.saturating_sub(n_cards * (card_w + 1) + 11) / 2;
+        //                                  |          |              ` Plus surrounding [], keys, and separator
+        //                                  |          ` Card width plus space in between
+        //                                  ` Reserve and foundation cards
+
+        if self.config.show_labels {
+            for i in 0..self.fc.reserve_count() {
+                screen.set_cursor(Cursor{
+                    line: 1,
+                    column: startx + 4 + i * (card_w + 1) + 1,
+                });
+                screen.write_char(self.config.slot_name(i));
+            }
+        }
+
+        screen.set_cursor(Cursor{
+            line: 2,
+            column: startx,
+        });
+
+        screen.write_str("R [ ");
+
+        for (i, r) in self.fc.reserve_slots().iter().enumerate() {
+            let focus = self.focus_style(Zone::Reserve, i);
+
+            match *r {
+                Some(c) => draw_card(screen, c, &self.config,
+                    self.card_style(c) | focus, compact),
+                None => screen.write_styled(None, None, focus, &blank)
+            }
+            screen.write_str(" ");
+        }
+
+        screen.write_str("] [ ");
+
+        for f in self.fc.foundation_slots() {
+            let focus = self.focus_style(Zone::Foundation, 0);
+
+            match *f {
+                Some(c) => draw_card(screen, c, &self.config,
+                    self.foundation_style(c) | focus, compact),
+                None => screen.write_styled(None, None, focus, &blank)
+            }
+            screen.write_str(" ");
+        }
+
+        screen.write_str("] T");
+    }
+
+    /// Draws reserve and foundation slots in a column from the given line,
+    /// with the left edge at the given column.
+    fn draw_side(&self, screen: &mut Screen, line: usize, column: usize, compact: bool) {
+        let card_w = card_width(compact);
+        let blank = "_".repeat(card_w);
+        let mut line = line;
+
+        screen.set_cursor(Cursor{line: line, column: column});
+        screen.write_styled(None, None, Style::UNDERLINE,
+            &format!("{:<w$}", "R", w = card_w + 2));
+
+        for (i, r) in self.fc.reserve_slots().iter().enumerate() {
+            line += 1;
+
+            if self.config.show_labels {
+                screen.write_at((line, column), &self.config.slot_name(i).to_string());
+            }
+
+            screen.set_cursor(Cursor{line: line, column: column + 2});
+
+            let focus = self.focus_style(Zone::Reserve, i);
+
+            match *r {
+                Some(c) => draw_card(screen, c, &self.config,
+                    self.card_style(c) | focus, compact),
+                None => screen.write_styled(None, None, focus, &blank)
+            }
+        }
+
+        line += 2;
+        screen.set_cursor(Cursor{line: line, column: column});
+        screen.write_styled(None, None, Style::UNDERLINE,
+            &format!("{:<w$}", "T", w = card_w + 2));
+
+        for f in self.fc.foundation_slots() {
+            line += 1;
+            screen.set_cursor(Cursor{line: line, column: column + 2});
+
+            let focus = self.focus_style(Zone::Foundation, 0);
+
+            match *f {
+                Some(c) => draw_card(screen, c, &self.config,
+                    self.foundation_style(c) | focus, compact),
+                None => screen.write_styled(None, None, focus, &blank)
+            }
+        }
+    }
+
+    fn draw_field(&mut self, game: &mut Game) {
+        let screen = game.screen();
+        let Size{columns, ..} = screen.size();
+
+        let compact = self.compact_field(columns);
+        // Width of a drawn card
+        let card_w = card_width(compact);
+        // Width of a tableau column, including space in between
+        let col_w = card_w + self.column_gap(columns, card_w);
+
+        if self.config.layout == Layout::Horizontal {
+            self.draw_row(screen, columns, compact);
+        }
+
+        if self.config.show_suit_counts {
+            self.draw_suit_counts(screen, columns);
+        }
+
+        let n_slots = self.fc.tableau_count();
+        let side_w = self.side_width(card_w);
+
+        let startx = columns.saturating_sub(n_slots * col_w + side_w) / 2;
+
+        if self.config.layout == Layout::Vertical {
+            // Separated from the tableau by a vertical line
+            let sep = startx + n_slots * col_w;
+            let height = self.fc.reserve_count() + self.fc.foundation_count() + 3;
+
+            for line in 4..4 + height {
+                screen.write_at((line, sep), "\u{2502}");
+            }
+
+            self.draw_side(screen, 4, sep + 2, compact);
+        }
+
+        screen.set_cursor(Cursor{
+            column: startx,
+            line: 4,
+        });
+        let header = self.tableau_header(col_w, card_w);
+
+        let focus_column = match self.focus {
+            Some(Focus{zone: Zone::Tableau, pos}) => Some(pos),
+            _ => None
+        };
+
+        if self.config.highlight_keys || focus_column.is_some() {
+            let active = if self.config.highlight_keys {
+                self.active_columns()
+            } else {
+                Vec::new()
+            };
+
+            // Labels of columns which may take part in a move are emboldened
+            // and the label of a focused column is reversed;
+            // the label of column `j` is at `1 + j * col_w`.
+            let mut buf = [0; 4];
+
+            for (i, ch) in header.chars().enumerate() {
+                let label = i != 0 && (i - 1) % col_w == 0;
+                let mut sty = Style::UNDERLINE;
+
+                if label && active.get((i - 1) / col_w) == Some(&true) {
+                    sty |= Style::BOLD;
+                }
+                if label && focus_column == Some((i - 1) / col_w) {
+                    sty |= Style::REVERSE;
+                }
+
+                screen.write_styled(None, None, sty, ch.encode_utf8(&mut buf));
+            }
+        } else {
+            screen.write_styled(None, None, Style::UNDERLINE, &header);
+        }
+
+        let max = self.tableau_rows();
+        let mut cols = self.fc.tableau_slots().iter()
+            .map(|t| t.iter()).collect::<Vec<_>>();
+
+        let gap = " ".repeat(col_w - card_w);
+        let blank = " ".repeat(card_w);
+
+        for i in 0..max {
+            if self.config.show_indices && startx >= 3 {
+                // Card positions are the same in every column,
+                // so one gutter serves all of them.
+                screen.set_cursor(Cursor{
+                    column: startx - 3,
+                    line: i + 5,
+                });
+                screen.write_str(&format!("{:>2}", i + 1));
+            }
+
+            screen.set_cursor(Cursor{
+                column: startx,
+                line: i + 5,
+            });
+
+            for (j, t) in cols.iter_mut().enumerate() {
+                let dealt = self.dealing.map_or(true, |n| i * n_slots + j < n);
+
+                match t.next() {
+                    Some(&c) if dealt =>
+                        draw_card(screen, c, &self.config,
+                            self.card_style(c), compact),
+                    _ => screen.write_str(&blank)
+                }
+                screen.write_str(&gap);
+            }
+        }
+
+        if self.config.show_labels {
+            screen.set_cursor(Cursor{
+                column: startx,
+                line: max + 5,
+            });
+            screen.write_str(&header);
+        }
+    }
+
+    /// Returns the labels of the tableau columns, spaced to fit over
+    /// columns of `col_w` holding cards of `card_w`.
+    fn tableau_header(&self, col_w: usize, card_w: usize) -> String {
+        let n_slots = self.fc.tableau_count();
+        let mut header = " ".to_owned();
+
+        for i in 0..n_slots {
+            if i != 0 {
+                header.push_str(&" ".repeat(col_w - 1));
+            }
+            header.push(self.config.slot_name(i));
+        }
+
+        // Extend the underline to the edge of the last card
+        while header.len() < (n_slots * col_w).saturating_sub(col_w - card_w) {
+            header.push(' ');
+        }
+
+        header
+    }
+
+    /// Returns the number of card rows drawn in the tableau,
+    /// which is zero once every column is cleared.
+    fn tableau_rows(&self) -> usize {
+        self.fc.tableau_slots().iter().map(|t| t.len()).max().unwrap_or(0)
+    }
+
+    /// Returns, for each tableau column, whether its key would take part
+    /// in a legal move: as a source when nothing is selected,
+    /// or as a destination for the selected source.
+    fn active_columns(&self) -> Vec<bool> {
+        let mut active = vec![false; self.fc.tableau_count()];
+
+        for mv in self.fc.legal_moves() {
+            let col = match (self.action, mv) {
+                (None, Move::TableauToFoundation(a)) |
+                (None, Move::TableauToReserve(a)) |
+                (None, Move::TableauToTableau(a, _, _)) => a,
+                // Pressing the source key again moves to reserve
+                (Some(Action::Slot(n)), Move::TableauToReserve(a)) if n as usize == a => a,
+                (Some(Action::Slot(n)), Move::TableauToTableau(a, b, _)) if n as usize == a => b,
+                (Some(Action::ReserveSlot(n)), Move::ReserveToTableau(a, b)) if n as usize == a => b,
+                _ => continue
+            };
+
+            active[col] = true;
+        }
+
+        active
+    }
+
+    /// Draws the next rank needed on foundation for each suit,
+    /// followed by the number of cards of that suit not yet on foundation.
+    fn draw_suit_counts(&self, screen: &mut Screen, columns: usize) {
+        let deck = self.fc.deck();
+        let parts = self.fc.foundation_iter()
+                .filter(|&(suit, _)| deck.has_suit(suit)).map(|(suit, f)| {
+            let left = deck.max_rank - f.map_or(0, |c| c.value.0);
+
+            let s = match self.fc.next_foundation_rank(suit) {
+                Some(next) => format!("{} {} ({})", self.config.suit_char(suit), next, left),
+                None => format!("{} done", self.config.suit_char(suit)),
+            };
+
+            (suit, s)
+        }).collect::<Vec<_>>();
+
+        let width = 6 + parts.iter().map(|&(_, ref s)| s.chars().count() + 2).sum::<usize>();
+
+        screen.set_cursor(Cursor{
+            line: 3,
+            column: columns.saturating_sub(width) / 2,
+        });
+        screen.write_str("Next: ");
+
+        for (suit, s) in parts {
+            screen.write_styled(self.config.suit_color(suit), None, Style::empty(), &s);
+            screen.write_str("  ");
+        }
+    }
+
+    fn draw_pause(&mut self, game: &mut Game) {
+        match self.pause_draw {
+            Draw::Pause => {
+                let screen = game.screen();
+                let Size{lines, columns} = screen.size();
+                let mid = lines / 2;
+                let center = columns / 2;
+                let col = center.saturating_sub(3);
+
+                screen.write_at((mid, col), "Paused");
+
+                let variant = if self.tutorial.is_some() {
+                    "Tutorial (Q to exit)".to_owned()
+                } else {
+                    format!("{} (V to change)", self.variant.name())
+                };
+                let col = center.saturating_sub(variant.len() / 2);
+
+                screen.write_at((mid + 2, col), &variant);
+
+                if self.tutorial.is_none() {
+                    let tutorial = "T for a tutorial";
+                    let col = center.saturating_sub(tutorial.len() / 2);
+
+                    screen.write_at((mid + 3, col), tutorial);
+                }
+
+                let collect = format!("Collection: {} (C to change)",
+                    self.config.sweep_preset().unwrap_or("Custom"));
+                let col = center.saturating_sub(collect.len() / 2);
+
+                screen.write_at((mid + 4, col), &collect);
+
+                if self.tutorial.is_none() {
+                    let deal = format!("Deal {} (R to restart)", self.seed);
+                    let col = center.saturating_sub(deal.len() / 2);
+
+                    screen.write_at((mid + 5, col), &deal);
+
+                    let challenge = format!("Challenges: {} of {} solved (H to view)",
+                        self.challenges_solved(), CHALLENGE_DEALS.len());
+                    let col = center.saturating_sub(challenge.len() / 2);
+
+                    screen.write_at((mid + 6, col), &challenge);
+                }
+            }
+            Draw::Help => self.draw_help(game),
+            Draw::Stats => self.draw_stats(game),
+            Draw::Victory => self.draw_victory(game),
+            Draw::Defeat => self.draw_summary(game, "Game over"),
+            Draw::Analysis => self.draw_analysis(game),
+            Draw::Challenge => self.draw_challenge(game),
+            Draw::Legality => self.draw_legality(game),
+            Draw::Replay => self.draw_replay(game),
+        }
+    }
+
+    /// Starts a replay of the finished game, from its first position.
+    fn begin_replay(&mut self) {
+        self.replay = Some(Replay{
+            positions: self.history(),
+            pos: 0,
+            speed: 1,
+            playing: true,
+            wait: REPLAY_SPEEDS[1] - 1,
+            from: self.pause_draw,
+        });
+        self.pause_draw = Draw::Replay;
+    }
+
+    fn replay_key(&mut self, key: Key) {
+        let rep = match self.replay {
+            Some(ref mut rep) => rep,
+            None => return
+        };
+
+        match key {
+            Key::Escape | Key::Char('q') => {
+                self.pause_draw = rep.from;
+                self.replay = None;
+            }
+            Key::Char(' ') => {
+                if rep.pos == rep.last() {
+                    rep.pos = 0;
+                }
+                rep.playing = !rep.playing;
+            }
+            Key::Left => {
+                let pos = rep.pos.saturating_sub(1);
+                rep.seek(pos);
+            }
+            Key::Right => {
+                let pos = rep.pos + 1;
+                rep.seek(pos);
+            }
+            Key::Home => rep.seek(0),
+            Key::End => {
+                let last = rep.last();
+                rep.seek(last);
+            }
+            Key::Char(n @ '0' ..= '9') => rep.seek_tenths((n as u8 - b'0') as usize),
+            Key::Char('+') | Key::Char('=') =>
+                rep.speed = min(rep.speed + 1, REPLAY_SPEEDS.len() - 1),
+            Key::Char('-') => rep.speed = rep.speed.saturating_sub(1),
+            _ => ()
+        }
+    }
+
+    /// Draws the position shown in replay, with a timeline beneath it.
+    fn draw_replay(&mut self, game: &mut Game) {
+        let (fc, pos, last, playing, speed) = match self.replay {
+            Some(ref rep) => (rep.positions[rep.pos].clone(), rep.pos, rep.last(),
+                rep.playing, rep.speed),
+            None => return
+        };
+
+        // The board is drawn as the position, then restored
+        let board = replace(&mut self.fc, fc);
+        self.draw_field(game);
+        self.fc = board;
+
+        let screen = game.screen();
+        let Size{lines, columns} = screen.size();
+
+        let label = format!("Move {} of {}", pos, last);
+        let state = if playing {
+            format!("Playing x{}", speed + 1)
+        } else {
+            "Stopped".to_owned()
+        };
+        let bar_w = columns.saturating_sub(label.len() + state.len() + 8).min(40);
+        let filled = if last == 0 { bar_w } else { bar_w * pos / last };
+
+        let line = format!("{}  [{}{}]  {}", label,
+            "=".repeat(filled), " ".repeat(bar_w - filled), state);
+        let keys = "Left, Right: Step  0-9: Seek  Space: Play  +, -: Speed  Esc: Back";
+
+        screen.write_at((lines.saturating_sub(3), columns.saturating_sub(line.len()) / 2),
+            &line);
+        screen.write_at((lines.saturating_sub(2), columns.saturating_sub(keys.len()) / 2),
+            keys);
+    }
+
+    fn draw_challenge(&mut self, game: &mut Game) {
+        let screen = game.screen();
+        let Size{lines, columns} = screen.size();
+
+        let n_lines = CHALLENGE_DEALS.len() + 4;
+        let startx = columns.saturating_sub(26) / 2;
+
+        screen.set_cursor(Cursor{
+            column: columns.saturating_sub(10) / 2,
+            line: lines.saturating_sub(n_lines) / 2,
+        });
+        screen.write_styled(None, None, Style::BOLD, "CHALLENGES");
+
+        // Skip a line
+        screen.next_line(startx);
+
+        let best_times = &self.stats.variant_mut(self.variant).best_times;
+
+        for (i, seed) in CHALLENGE_DEALS.iter().enumerate() {
+            let best = match best_times.get(seed) {
+                Some(&t) => time_str(t),
+                None => "unsolved".to_owned()
+            };
+            let sty = if i == self.challenge { Style::REVERSE } else { Style::empty() };
+
+            screen.next_line(startx);
+            screen.write_styled(None, None, sty,
+                &format!("{:>2}. Deal {:>7} {:>8}", i + 1, seed, best));
+        }
+
+        // Skip a line
+        screen.next_line(startx);
+
+        screen.next_line(startx);
+        screen.write_str("Enter: Play  N: Next unsolved");
+    }
+
+    fn draw_help(&mut self, game: &mut Game) {
+        let screen = game.screen();
+        let Size{lines, columns} = screen.size();
+
+        let n_lines = HELP_TEXT.lines().count();
+        let max_w = HELP_TEXT.lines().map(|l| l.len()).max().unwrap();
+
+        screen.set_cursor(Cursor{
+            line: lines.saturating_sub(n_lines).saturating_sub(2) / 2,
+            column: columns.saturating_sub(4) / 2,
+        });
+        screen.write_styled(None, None, Style::BOLD, "HELP");
+
+        let startx = columns.saturating_sub(max_w) / 2;
+
+        // Skip a full line
+        screen.next_line(startx);
+
+        for line in HELP_TEXT.lines() {
+            screen.next_line(startx);
+            screen.write_str(line);
+        }
+    }
+
+    fn draw_stats(&mut self, game: &mut Game) {
+        let screen = game.screen();
+        let Size{lines, columns} = screen.size();
+        let n_lines = 7;
+        let stats = self.stats.variant_mut(self.variant);
+
+        let startx = columns.saturating_sub(23) / 2;
+        let starty = lines.saturating_sub(n_lines) / 2 - 3;
+
+        screen.set_cursor(Cursor{
+            column: columns.saturating_sub(5) / 2,
+            line: starty,
+        });
+        screen.write_styled(None, None, Style::BOLD, "STATS");
+
+        screen.next_line(startx);
+        screen.write_str(&format!("Variant: {:>14}", self.variant.name()));
+
+        // Skip a line
+        screen.next_line(startx);
+
+        screen.next_line(startx);
+        screen.write_str(&format!("Games played:   {:>7}", stats.games));
+        screen.next_line(startx);
+        screen.write_str(&format!("Games won:      {:>7}", stats.won));
+        screen.next_line(startx);
+        screen.write_str(&format!("Win rate:       {:>6}%", stats.win_rate()));
+        screen.next_line(startx);
+        screen.write_str(&format!("Abandoned:      {:>7}", stats.abandoned));
+
+        if self.config.track_hints {
+            screen.next_line(startx);
+            screen.write_str(&format!("Won w/o hints:  {:>7}", stats.hint_free_wins));
+        }
+
+        // Skip a line
+        screen.next_line(startx);
+
+        screen.next_line(startx);
+        screen.write_str(&format!("Longest streak: {:>7}", stats.longest_streak));
+        screen.next_line(startx);
+        screen.write_str(&format!("Current streak: {:>7}", stats.current_streak));
+
+        if self.config.streak_freeze_wins != 0 {
+            screen.next_line(startx);
+            screen.write_str(&format!("Streak freezes: {:>7}", stats.streak_freezes));
+        }
+
+        screen.next_line(startx);
+        screen.write_str("Recent: ");
+
+        if stats.recent.is_empty() {
+            screen.write_str(&format!("{:>15}", "none"));
+        } else {
+            // Results are aligned to the right, the newest last
+            screen.write_str(&" ".repeat(RECENT_RESULTS - stats.recent.len()));
+
+            for &won in &stats.recent {
+                if won {
+                    screen.write_styled(Some(TermColor::Green), None, Style::empty(), "\u{2713}");
+                } else {
+                    screen.write_styled(Some(TermColor::Red), None, Style::empty(), "\u{2717}");
+                }
+            }
+        }
+
+        // Skip a line
+        screen.next_line(startx);
+
+        screen.next_line(startx);
+        screen.write_str(&format!("Average time:   {:>7}",
+            time_str(stats.average_time())));
+        screen.next_line(startx);
+        screen.write_str(&format!("Lowest time:    {:>7}",
+            time_str(stats.lowest_time)));
+        screen.next_line(startx);
+        screen.write_str(&format!("Highest time:   {:>7}",
+            time_str(stats.highest_time)));
+
+        // Skip a line
+        screen.next_line(startx);
+
+        screen.next_line(startx);
+        screen.write_str("Press 'c' to clear");
+    }
+
+    fn draw_analysis(&mut self, game: &mut Game) {
+        let screen = game.screen();
+        let Size{lines, columns} = screen.size();
+
+        let analysis = match self.analysis {
+            Some(ref a) => a,
+            None => return
+        };
+
+        let mut text = Vec::new();
+
+        if analysis.done() {
+            let losing = analysis.losing_moves();
+
+            if losing.is_empty() {
+                text.push("No losing moves found".to_owned());
+            } else {
+                let moves = losing.iter()
+                    .map(|n| n.to_string()).collect::<Vec<_>>();
+                text.push(format!("Game became unwinnable after move {}",
+                    moves.join(", ")));
+            }
+
+            let unknown = analysis.unknown();
+
+            if unknown != 0 {
+                text.push(format!("{} of {} positions could not be resolved",
+                    unknown, analysis.positions.len()));
+            }
+        } else {
+            text.push(format!("Analyzing position {} of {}",
+                analysis.results.len() + 1, analysis.positions.len()));
+        }
+
+        let max_w = text.iter().map(|l| l.len()).max().unwrap_or(0);
+
+        screen.set_cursor(Cursor{
+            line: lines.saturating_sub(text.len() + 2) / 2,
+            column: columns.saturating_sub(8) / 2,
+        });
+        screen.write_styled(None, None, Style::BOLD, "ANALYSIS");
+
+        let startx = columns.saturating_sub(max_w) / 2;
+
+        // Skip a full line
+        screen.next_line(startx);
+
+        for line in &text {
+            screen.next_line(startx);
+            screen.write_str(line);
+        }
+    }
+
+    fn draw_legality(&mut self, game: &mut Game) {
+        let screen = game.screen();
+        let Size{lines, columns} = screen.size();
+
+        let text = self.legality_lines();
+        let max_w = text.iter().map(|l| l.len()).max().unwrap_or(0);
+
+        screen.set_cursor(Cursor{
+            line: lines.saturating_sub(text.len() + 2) / 2,
+            column: columns.saturating_sub(8) / 2,
+        });
+        screen.write_styled(None, None, Style::BOLD, "LEGALITY");
+
+        let startx = columns.saturating_sub(max_w) / 2;
+
+        // Skip a full line
+        screen.next_line(startx);
+
+        for line in &text {
+            screen.next_line(startx);
+            screen.write_str(line);
+        }
+    }
+
+    /// Describes whether each move from the selected slot is legal,
+    /// and, if not, why.
+    fn legality_lines(&self) -> Vec<String> {
+        use self::Action::*;
+
+        fn result(fc: &FreeCell, mv: Move) -> String {
+            match fc.clone().apply_move(mv) {
+                Ok(()) if mv.is_to_foundation() => {
+                    let safe = fc.moved_card(mv)
+                        .map_or(false, |c| fc.is_safe_foundation_move(c));
+
+                    if safe { "ok, safe" } else { "ok, risky" }.to_owned()
+                }
+                Ok(()) => "ok".to_owned(),
+                Err(e) => e.to_string()
+            }
+        }
+
+        let mut text = Vec::new();
+
+        match self.action {
+            Some(ReserveSlot(a)) => {
+                let a = a as usize;
+
+                text.push(format!("From reserve {}", self.config.slot_name(a)));
+                text.push(format!("{:<12}{}", "Foundation",
+                    result(&self.fc, Move::ReserveToFoundation(a))));
+
+                for b in 0..self.fc.tableau_count() {
+                    text.push(format!("{:<12}{}", format!("Column {}", self.config.slot_name(b)),
+                        result(&self.fc, Move::ReserveToTableau(a, b))));
+                }
+            }
+            Some(Slot(a)) | Some(SlotToReserve(a)) => {
+                let a = a as usize;
+
+                text.push(format!("From column {}", self.config.slot_name(a)));
+                text.push(format!("{:<12}{}", "Foundation",
+                    result(&self.fc, Move::TableauToFoundation(a))));
+                text.push(format!("{:<12}{}", "Reserve",
+                    result(&self.fc, Move::TableauToReserve(a))));
+
+                for b in (0..self.fc.tableau_count()).filter(|&b| b != a) {
+                    let r = match self.fc.tableau_move_size(a, b) {
+                        Ok(1) => "ok".to_owned(),
+                        Ok(n) => format!("ok, {} cards", n),
+                        Err(e) => e.to_string()
+                    };
+
+                    let column = format!("Column {}", self.config.slot_name(b));
+                    text.push(format!("{:<12}{}", column, r));
+                }
+            }
+            _ => text.push("Select a slot to test its moves".to_owned())
+        }
+
+        text
+    }
+
+    /// Returns each board state reached in the current game, oldest first.
+    fn history(&self) -> Vec<FreeCell> {
+        let mut history = self.undo.clone();
+
+        // The newest state is kept in `undo` only after an undo
+        if self.undo_index == self.undo.len() {
+            history.push(self.fc.clone());
+        }
+
+        history
+    }
+
+    fn begin_analysis(&mut self) {
+        if self.in_progress() {
+            self.hints += 1;
+        }
+
+        self.analysis = Some(Analysis{
+            positions: self.history(),
+            results: Vec::new(),
+        });
+        self.pause_draw = Draw::Analysis;
+    }
+
+    fn draw_victory(&mut self, game: &mut Game) {
+        if let Some(ref cel) = self.celebration {
+            let screen = game.screen();
+
+            for &(card, line, column) in &cel.trail {
+                screen.set_cursor(Cursor{line: line, column: column});
+                draw_card(screen, card, &self.config,
+                    Style::empty(), false);
+            }
+            return;
+        }
+
+        self.draw_summary(game, "You won!");
+    }
+
+    fn draw_summary(&mut self, game: &mut Game, title: &str) {
+        let screen = game.screen();
+        let Size{lines, columns} = screen.size();
+
+        let sum = match self.summary {
+            Some(ref sum) => sum,
+            None => return
+        };
+
+        let n_lines = 11;
+        let startx = columns.saturating_sub(21) / 2;
+
+        screen.set_cursor(Cursor{
+            column: columns.saturating_sub(title.chars().count()) / 2,
+            line: lines.saturating_sub(n_lines) / 2,
+        });
+        screen.write_styled(None, None, Style::BOLD, title);
+
+        // Skip a line
+        screen.next_line(startx);
+
+        screen.next_line(startx);
+        screen.write_str(&format!("Deal:      {:>10}", sum.seed));
+        screen.next_line(startx);
+        screen.write_str(&format!("Time:      {:>10}", time_str(sum.time)));
+        if sum.new_best {
+            screen.write_styled(None, None, Style::BOLD, " Best!");
+        }
+        screen.next_line(startx);
+        screen.write_str(&format!("Moves:     {:>10}", sum.moves));
+        if sum.par_pending {
+            screen.next_line(startx);
+            screen.write_str(&format!("Par:       {:>10}", "finding..."));
+        } else if let Some(par) = sum.par {
+            screen.next_line(startx);
+            screen.write_str(&format!("Par:       {:>10}", par));
+            screen.next_line(startx);
+            screen.write_str(&format!("Rating:    {:>10}", rating(sum.moves, par)));
+        }
+        screen.next_line(startx);
+        screen.write_str(&format!("Undos:     {:>10}", sum.undos));
+        if self.config.track_hints {
+            screen.next_line(startx);
+            screen.write_str(&format!("Hints:     {:>10}", sum.hints));
+            if sum.hints == 0 && self.game_won {
+                screen.write_styled(None, None, Style::BOLD, " No hints!");
+            }
+        }
+        screen.next_line(startx);
+        screen.write_str(&format!("Streak:    {:>10}", sum.streak));
+
+        // Skip a line
+        screen.next_line(startx);
+
+        screen.next_line(startx);
+        screen.write_str("N: New game  R: Replay deal  M: Menu  W: Watch");
+
+        if self.auto_deal_at.is_some() && self.celebration.is_none() {
+            screen.next_line(startx);
+            screen.write_str("New game soon; any key to stay");
+        }
+    }
+
+    /// Restores a pending action after a key referring to an empty
+    /// or missing slot, unless configured to cancel it.
+    fn keep_source(&mut self, old: Action) {
+        if self.config.keep_source {
+            self.action = Some(old);
+        }
+    }
+
+    /// Returns the effect of pressing a tableau key twice.
+    ///
+    /// The tutorial teaches the default, so it is used there whatever
+    /// the configured `double_tap`.
+    fn double_tap(&self) -> DoubleTap {
+        if self.tutorial.is_some() {
+            DoubleTap::Move
+        } else {
+            self.config.double_tap
+        }
+    }
+
+    fn action(&mut self, game: &mut Game, action: Action) {
+        use self::Action::*;
+
+        game.redraw();
+
+        let old = match self.action.take() {
+            Some(act) => act,
+            None => {
+                match action {
+                    Foundation => self.show_message(game, "Invalid action", one_sec()),
+                    Slot(n) if n as usize >= self.fc.tableau_count() => {
+                        self.show_message(game, "Invalid tableau slot", one_sec());
+                    }
+                    Slot(n) if self.fc.tableau(n as usize).is_empty() => {
+                        self.show_message(game, "Tableau slot is empty", one_sec());
+                    }
+                    _ => self.action = Some(action)
+                }
+                return;
+            }
+        };
+
+        let prev = self.fc.clone();
 
         match (old, action) {
-            (Reserve, Slot(n @ 0 ..= 3)) => {
+            (ReserveSlot(_), Slot(b)) | (Slot(_), Slot(b))
+                    if b as usize >= self.fc.tableau_count() => {
+                self.show_message(game, "Invalid tableau slot", one_sec());
+                self.keep_source(old);
+            }
+            (Reserve, Slot(n)) if (n as usize) < self.fc.reserve_count() => {
                 if self.fc.reserve(n as usize).is_some() {
                     self.action = Some(Action::ReserveSlot(n));
                 } else {
-                    game.set_message("Reserve slot is empty", one_sec());
+                    self.show_message(game, "Reserve slot is empty", one_sec());
+                    self.keep_source(old);
                 }
             }
             (Reserve, Slot(_)) => {
-                game.set_message("Invalid reserve slot", one_sec())
+                self.show_message(game, "Invalid reserve slot", one_sec());
+                self.keep_source(old);
             }
             (ReserveSlot(n), Foundation) => {
                 if let Some(c) = self.fc.reserve(n as usize) {
@@ -549,10 +3006,10 @@ impl FreeCellGame {
                         self.fc.remove_reserve(n as usize);
                         self.fc.add_to_foundation(c);
                     } else {
-                        game.set_message("Cannot move to foundation", one_sec());
+                        self.show_message(game, "Cannot move to foundation", one_sec());
                     }
                 } else {
-                    game.set_message("Reserve slot is empty", one_sec())
+                    self.show_message(game, "Reserve slot is empty", one_sec())
                 }
             }
             (ReserveSlot(a), Slot(b)) => {
@@ -562,10 +3019,10 @@ impl FreeCellGame {
                         self.fc.remove_reserve(a as usize);
                         self.fc.add_to_tableau(c, b as usize);
                     } else {
-                        game.set_message("Cannot move to tableau", one_sec());
+                        self.show_message(game, "Cannot move to tableau", one_sec());
                     }
                 } else {
-                    game.set_message("Reserve slot is empty", one_sec());
+                    self.show_message(game, "Reserve slot is empty", one_sec());
                 }
             }
             (Slot(a), Foundation) => {
@@ -576,113 +3033,709 @@ impl FreeCellGame {
                             self.fc.pop_tableau(a as usize);
                             self.fc.add_to_foundation(c);
                         } else {
-                            game.set_message("Cannot move to foundation", one_sec());
+                            self.show_message(game, "Cannot move to foundation", one_sec());
                         }
                     }
-                    None => game.set_message("Tableau slot is empty", one_sec())
+                    None => self.show_message(game, "Tableau slot is empty", one_sec())
                 }
             }
             (Slot(a), Reserve) => {
-                self.move_to_reserve(game, a as usize);
+                if self.fc.reserve_free() {
+                    self.action = Some(SlotToReserve(a));
+                } else {
+                    self.show_message(game, "No free reserve slots", one_sec());
+                }
             }
             (Slot(a), Slot(b)) if a == b => {
-                self.move_to_reserve(game, a as usize);
+                match self.double_tap() {
+                    DoubleTap::Move => self.move_to_reserve(game, a as usize, None),
+                    DoubleTap::SelectReserve if self.fc.reserve_free() =>
+                        self.action = Some(SlotToReserve(a)),
+                    DoubleTap::SelectReserve =>
+                        self.show_message(game, "No free reserve slots", one_sec()),
+                    DoubleTap::Off => self.action = Some(old),
+                }
+            }
+            (SlotToReserve(a), Reserve) => {
+                self.move_to_reserve(game, a as usize, None);
+            }
+            (SlotToReserve(a), Slot(n)) => {
+                self.move_to_reserve(game, a as usize, Some(n as usize));
             }
             (Slot(a), Slot(b)) => {
                 if self.fc.tableau(a as usize).is_empty() {
-                    game.set_message("Tableau slot is empty", one_sec());
+                    self.show_message(game, "Tableau slot is empty", one_sec());
                 } else {
                     self.move_tableau(game, a as usize, b as usize);
                 }
             }
             _ => {
-                game.set_message("Invalid action", one_sec());
+                self.show_message(game, "Invalid action", one_sec());
+            }
+        }
+
+        self.mark_moved(&prev, false);
+        self.hold_exposed(&prev);
+        self.try_sweep = true;
+    }
+
+    /// Holds back from sweeping the cards which a move from the given state
+    /// exposed at the top of a tableau column.
+    fn hold_exposed(&mut self, prev: &FreeCell) {
+        self.placed = self.fc.tableau_slots().iter().zip(prev.tableau_slots())
+            .filter(|&(t, old)| t.len() > old.len())
+            .filter_map(|(t, _)| t.last().cloned())
+            .next();
+
+        if self.config.sweep_grace == 0 {
+            return;
+        }
+
+        let exposed = self.fc.tableau_slots().iter().zip(prev.tableau_slots())
+            .filter_map(|(t, old)| match t.last() {
+                Some(c) if old.last() != Some(c) => Some(*c),
+                _ => None
+            })
+            .collect::<Vec<_>>();
+
+        if !exposed.is_empty() {
+            self.held = exposed;
+            self.held_since = Instant::now();
+        }
+    }
+
+    /// Returns the cards to be left in place by automatic foundation moves:
+    /// those locked by the player, the card last placed on the tableau,
+    /// and exposed cards still in their grace period.
+    ///
+    /// Once every remaining card can be moved to foundation, none are held.
+    fn sweep_exclusions(&self) -> Vec<Card> {
+        let mut hold = self.locked.clone();
+
+        if self.config.keep_placed {
+            hold.extend(self.placed);
+        }
+        if self.holding() {
+            hold.extend_from_slice(&self.held);
+        }
+
+        if !hold.is_empty() && self.fc.finish_moves().is_some() {
+            hold.clear();
+        }
+
+        hold
+    }
+
+    /// Locks or unlocks the top card of the selected slot,
+    /// keeping it from automatic foundation moves.
+    fn toggle_lock(&mut self, game: &mut Game) {
+        use self::Action::*;
+
+        let card = match self.action.take() {
+            Some(Slot(n)) | Some(SlotToReserve(n)) =>
+                self.fc.tableau(n as usize).last().cloned(),
+            Some(ReserveSlot(n)) => self.fc.reserve_slots()[n as usize],
+            _ => {
+                self.show_message(game, "Select a slot to lock its card", one_sec());
+                return;
+            }
+        };
+
+        match card {
+            Some(c) => match self.locked.iter().position(|&l| l == c) {
+                Some(i) => {
+                    self.locked.remove(i);
+                    self.show_message(game, &format!("Unlocked {}", self.config.card_name(c)),
+                        one_sec());
+                    self.try_sweep = true;
+                }
+                None => {
+                    self.locked.push(c);
+                    self.show_message(game, &format!("Locked {}; it will not be moved \
+                        to foundation automatically", self.config.card_name(c)), one_sec());
+                }
+            },
+            None => self.show_message(game, "Slot is empty", one_sec())
+        }
+    }
+
+    /// Returns whether exposed cards are still being held back from sweeping.
+    fn holding(&self) -> bool {
+        !self.held.is_empty() &&
+            self.held_since.elapsed() < Duration::from_secs(self.config.sweep_grace)
+    }
+
+    /// Marks the cards which have moved since the given state.
+    ///
+    /// Unless `append` is set, marks from a previous move are cleared.
+    fn mark_moved(&mut self, prev: &FreeCell, append: bool) {
+        let old = card_places(prev);
+        let moved = card_places(&self.fc).into_iter()
+            .filter(|place| !old.contains(place))
+            .collect::<Vec<_>>();
+
+        if self.config.announce_events {
+            let events = self.fc.events_since(prev);
+            self.events.extend(events);
+        }
+
+        if moved.is_empty() {
+            return;
+        }
+
+        if let Some(&(card, _)) = moved.iter().rev()
+                .find(|&&(_, pos)| pos == Position::Foundation) {
+            self.last_foundation = Some(card.suit);
+        }
+
+        if !append {
+            self.recent.clear();
+        }
+
+        self.recent.extend(moved.into_iter().map(|(card, _)| card));
+        self.recent_ticks = RECENT_TICKS;
+    }
+
+    /// Clears marks of recently moved cards, and their events.
+    fn clear_moved(&mut self) {
+        self.recent.clear();
+        self.recent_ticks = 0;
+        self.events.clear();
+    }
+
+    /// Shows on the message line the events of recent moves.
+    fn announce_events(&mut self, game: &mut Game) {
+        if self.events.is_empty() {
+            return;
+        }
+
+        let events = take(&mut self.events);
+
+        if game.paused() {
+            // A won game has its own screen
+            return;
+        }
+
+        let msg = events.iter().map(|&ev| match ev {
+            Event::ColumnEmptied(pos) => format!("Column {} cleared!", self.config.slot_name(pos)),
+            Event::AceHome(suit) => format!("{} ace home", self.config.suit_char(suit)),
+            Event::SuitComplete(suit) => format!("{} complete!", self.config.suit_char(suit)),
+            Event::ReserveFilled => "Last free cell filled".to_string(),
+        }).collect::<Vec<_>>().join("  ");
+
+        self.show_message(game, &msg, Some(Duration::from_secs(2)));
+    }
+
+    /// Returns whether a move leading to the board `next` may be made,
+    /// asking first if it would fill the last free reserve slot.
+    ///
+    /// The move is declined if the board changes while the question is shown,
+    /// as when a card held back is moved to foundation.
+    fn confirm_fill(&mut self, game: &mut Game, next: &FreeCell) -> bool {
+        if !self.config.confirm_last_cell || !self.fc.reserve_free() || next.reserve_free() {
+            return true;
+        }
+
+        let board = self.fc.to_compact_string();
+
+        self.confirm(game, "This fills your last free cell \u{2014} continue?") &&
+            self.fc.to_compact_string() == board
+    }
+
+    /// Moves the card most recently added to foundation to a free reserve slot.
+    fn take_back_foundation(&mut self, game: &mut Game) {
+        let suit = match self.last_foundation {
+            Some(suit) if self.fc.foundation(suit).is_some() => suit,
+            _ => {
+                self.show_message(game, "No foundation card to take back", one_sec());
+                return;
+            }
+        };
+
+        if !self.fc.reserve_free() {
+            self.show_message(game, "No free reserve slots", one_sec());
+            return;
+        }
+
+        let prev = self.fc.clone();
+        let mut next = prev.clone();
+        let card = next.remove_from_foundation(suit).unwrap();
+        next.add_to_reserve(card);
+
+        if !self.confirm_fill(game, &next) {
+            return;
+        }
+
+        self.push_undo();
+        self.fc = next;
+        self.mark_moved(&prev, false);
+        self.last_foundation = None;
+        game.redraw();
+    }
+
+    /// Moves the top card of tableau slot `a` to the given reserve slot;
+    /// or to the first free reserve slot, if `None`.
+    fn move_to_reserve(&mut self, game: &mut Game, a: usize, pos: Option<usize>) {
+        let pos = match self.reserve_target(a, pos) {
+            Ok(pos) => pos,
+            Err(msg) => {
+                self.show_message(game, msg, one_sec());
+                return;
+            }
+        };
+
+        let mut next = self.fc.clone();
+        let c = next.pop_tableau(a);
+        next.add_to_reserve_at(c, pos);
+
+        if !self.confirm_fill(game, &next) {
+            return;
+        }
+
+        self.push_undo();
+        self.fc = next;
+    }
+
+    /// Returns the reserve slot to which the top card of tableau slot `a`
+    /// would be moved: the given slot; or the first free slot, if `None`.
+    /// Otherwise, returns the reason the card cannot be moved.
+    fn reserve_target(&self, a: usize, pos: Option<usize>) -> Result<usize, &'static str> {
+        if self.fc.tableau(a).is_empty() {
+            return Err("Tableau slot is empty");
+        }
+
+        match pos {
+            Some(pos) if pos >= self.fc.reserve_count() => Err("Invalid reserve slot"),
+            Some(pos) if self.fc.reserve(pos).is_some() => Err("Reserve slot is occupied"),
+            Some(pos) => Ok(pos),
+            None => self.fc.free_reserve_slot().ok_or("No free reserve slots")
+        }
+    }
+
+    fn move_tableau(&mut self, game: &mut Game, a: usize, b: usize) {
+        match self.fc.tableau_move_size(a, b) {
+            Ok(n) => {
+                self.push_undo();
+                self.fc.move_tableau_group(a, b, n);
+            }
+            Err(MoveError::NotEnoughSpace) => {
+                let cap = if self.fc.tableau(b).is_empty() {
+                    self.fc.max_group_to_empty()
+                } else {
+                    self.fc.max_group_to_nonempty()
+                };
+                self.show_message(game, &format!("{} (at most {} cards)",
+                    MoveError::NotEnoughSpace, cap), one_sec());
+            }
+            Err(e) => self.show_message(game, &e.to_string(), one_sec())
+        }
+    }
+
+    /// Returns the card at the selected source, the number of cards
+    /// the largest valid move from that source would carry,
+    /// and a description of the source.
+    fn pending_move(&self) -> Option<(Card, usize, String)> {
+        use self::Action::*;
+
+        match self.action {
+            Some(ReserveSlot(n)) => {
+                self.fc.reserve(n as usize).map(|c|
+                    (c, 1, format!("reserve {}", self.config.slot_name(n as usize))))
+            }
+            Some(Slot(n)) => {
+                let a = n as usize;
+                let tab = self.fc.tableau(a);
+
+                if tab.is_empty() {
+                    return None;
+                }
+
+                let size = (0..self.fc.tableau_count())
+                    .filter(|&b| b != a)
+                    .filter_map(|b| self.fc.tableau_move_size(a, b).ok())
+                    .max().unwrap_or(1);
+
+                Some((tab[tab.len() - size], size,
+                    format!("column {}", self.config.slot_name(a))))
+            }
+            Some(SlotToReserve(n)) => {
+                self.fc.tableau(n as usize).last().map(|&c|
+                    (c, 1, format!("column {}", self.config.slot_name(n as usize))))
+            }
+            _ => None
+        }
+    }
+
+    fn sweep_step(&mut self, game: &mut Game) {
+        if self.sweep_wait != 0 {
+            self.sweep_wait -= 1;
+            return;
+        }
+
+        if self.finishing {
+            self.finish_step(game);
+            return;
+        }
+
+        let n = match self.config.sweep_cards {
+            0 => u32::max_value(),
+            n => n
+        };
+        let prev = self.fc.clone();
+        let holding = self.holding();
+        let hold = self.sweep_exclusions();
+        let autoplay = self.config.autoplay == AutoplayMode::Safe;
+
+        if autoplay && self.fc.sweep_step_except(n, &hold) {
+            self.sweep_wait = self.config.sweep_ticks - 1;
+            self.mark_moved(&prev, true);
+            game.redraw();
+        } else if !holding {
+            self.try_sweep = false;
+
+            if self.dead_end() {
+                self.game_lost(game);
+            } else {
+                self.check_stall(game);
+
+                if self.config.autoplay == AutoplayMode::Suggest {
+                    self.suggest_foundation(game);
+                }
+                self.offer_finish(game);
+            }
+        }
+    }
+
+    /// Shows the keys which move to foundation a card which may safely
+    /// be moved there, if there is one.
+    fn suggest_foundation(&self, game: &mut Game) {
+        let reserve = self.fc.reserve_slots().iter().enumerate()
+            .filter_map(|(i, r)| r.map(|c| (c, format!("R, {}", self.config.slot_name(i)))));
+        let tableau = self.fc.tableau_slots().iter().enumerate()
+            .filter_map(|(i, t)| t.last().map(|&c| (c, self.config.slot_name(i).to_string())));
+
+        let found = reserve.chain(tableau)
+            .find(|&(c, _)| self.fc.should_move_to_foundation(c));
+
+        if let Some((c, keys)) = found {
+            self.show_message(game, &format!("Press {}, T to move {} to foundation",
+                keys, self.config.card_name(c)), Some(Duration::from_secs(3)));
+        }
+    }
+
+    /// Completes at once any foundation moves being animated,
+    /// leaving in place cards held back after a move.
+    fn skip_sweep(&mut self, game: &mut Game) {
+        let prev = self.fc.clone();
+
+        if self.finishing {
+            if let Some(moves) = self.fc.finish_moves() {
+                for mv in moves {
+                    self.fc.apply_move(mv).expect("legal move failed");
+                }
+            }
+            self.finishing = false;
+            self.end_undo_group();
+        } else if self.try_sweep && self.action.is_none() &&
+                self.config.autoplay == AutoplayMode::Safe {
+            let hold = self.sweep_exclusions();
+
+            while self.fc.sweep_step_except(u32::max_value(), &hold) {}
+        } else {
+            return;
+        }
+
+        self.sweep_wait = 0;
+        self.mark_moved(&prev, true);
+        game.redraw();
+    }
+
+    /// Tells the player how to move all remaining cards to foundation,
+    /// if no other moves are needed to win.
+    ///
+    /// This is shown once until the game is again one which needs other moves;
+    /// play goes on as usual if the player makes another move instead.
+    fn offer_finish(&mut self, game: &mut Game) {
+        if self.take_finish_offer() {
+            self.show_message(game, "Press Shift-E to finish the game",
+                Some(Duration::from_secs(3)));
+        }
+    }
+
+    /// Returns whether finishing should now be offered,
+    /// noting that it has been.
+    fn take_finish_offer(&mut self) -> bool {
+        let offer = self.can_finish() && !self.finish_offered;
+
+        self.finish_offered = self.can_finish();
+        offer
+    }
+
+    /// Returns whether the game can be won by foundation moves alone.
+    fn can_finish(&self) -> bool {
+        self.tutorial.is_none() && !self.fc.game_over() &&
+            self.fc.finish_moves().is_some()
+    }
+
+    /// Begins moving all remaining cards to foundation,
+    /// if no other moves are needed to win.
+    fn start_finish(&mut self, game: &mut Game) {
+        if self.finishing {
+            return;
+        } else if !self.can_finish() {
+            self.show_message(game, "Other moves are needed to win", one_sec());
+            return;
+        }
+
+        self.clear_action(game);
+        self.begin_undo_group();
+        self.finishing = true;
+        self.try_sweep = true;
+    }
+
+    /// Moves cards to foundation while finishing the game,
+    /// at the pace of automatic foundation moves.
+    fn finish_step(&mut self, game: &mut Game) {
+        let moves = match self.fc.finish_moves() {
+            Some(moves) => moves,
+            None => {
+                self.finishing = false;
+                self.end_undo_group();
+                return;
             }
+        };
+        let n = match self.config.sweep_cards {
+            0 => moves.len(),
+            n => n as usize
+        };
+        let prev = self.fc.clone();
+
+        for &mv in moves.iter().take(n) {
+            self.fc.apply_move(mv).expect("legal move failed");
         }
 
-        self.try_sweep = true;
-    }
+        self.sweep_wait = self.config.sweep_ticks - 1;
+        self.mark_moved(&prev, true);
+        game.redraw();
 
-    fn move_to_reserve(&mut self, game: &mut Game, a: usize) {
-        if self.fc.tableau(a as usize).is_empty() {
-            game.set_message("Tableau slot is empty", one_sec());
-        } else {
-            if self.fc.reserve_free() {
-                self.push_undo();
-                let c = self.fc.pop_tableau(a as usize);
-                self.fc.add_to_reserve(c);
-            } else {
-                game.set_message("No free reserve slots", one_sec());
-            }
+        if self.fc.game_over() {
+            self.finishing = false;
+            self.end_undo_group();
         }
     }
 
-    fn move_tableau(&mut self, game: &mut Game, a: usize, b: usize) {
-        match self.fc.tableau(b).last().cloned() {
-            Some(top) => {
-                let mut mov = None;
-
-                {
-                    let tab_a = self.fc.tableau(a);
-                    let n = tab_a.len();
-                    let size = self.fc.group_size(a);
-                    let cap = self.fc.move_capacity(a, b);
-
-                    for i in 1..size + 1 {
-                        let c = tab_a[n - i];
-                        if c.can_top(top) {
-                            if i > cap {
-                                game.set_message("Not enough reserve slots to move", one_sec());
-                                return;
-                            } else {
-                                mov = Some((a, b, i));
-                                break;
-                            }
-                        }
-                    }
-                }
+    /// Returns whether the game can no longer be won.
+    fn dead_end(&self) -> bool {
+        self.tutorial.is_none() && !self.recorded && !self.fc.game_over() &&
+            self.fc.legal_moves().is_empty()
+    }
 
-                if let Some((a, b, i)) = mov {
-                    self.push_undo();
-                    self.fc.move_tableau_group(a, b, i);
-                } else {
-                    game.set_message("Cannot move cards", one_sec());
-                }
+    fn action_str(&self) -> String {
+        use self::Action::*;
+
+        match self.action {
+            Some(Reserve) => "R".to_owned(),
+            Some(ReserveSlot(n)) => format!("R {}", self.config.slot_name(n as usize)),
+            Some(Slot(n)) => format!("{}", self.config.slot_name(n as usize)),
+            Some(SlotToReserve(n)) => format!("{} R", self.config.slot_name(n as usize)),
+            _ => "".to_owned(),
+        }
+    }
+
+    /// Shows a move which leads to a win, if the solver can find one.
+    fn hint(&mut self, game: &mut Game) {
+        match solve(&self.fc, HINT_BUDGET) {
+            Solution::Solved(moves) => {
+                let msg = match moves.first() {
+                    Some(&mv) => format!("Hint: {}", self.describe_move(mv)),
+                    None => "No moves needed".to_owned()
+                };
+
+                self.hints += 1;
+                self.show_message(game, &msg, Some(Duration::from_secs(3)));
             }
+            Solution::Unsolvable =>
+                self.show_message(game, "No winning moves remain", one_sec()),
+            Solution::Unknown =>
+                self.show_message(game, "No hint found", one_sec()),
+        }
+    }
+
+    /// Makes a reasonable move at once, chosen by simple rules
+    /// rather than by the solver; counted as a hint.
+    fn assist(&mut self, game: &mut Game) {
+        let mv = match self.fc.best_move() {
+            Some(mv) => mv,
             None => {
-                self.push_undo();
-                let cap = self.fc.move_capacity(a, b);
-                self.fc.move_tableau_group(a, b, cap);
+                self.show_message(game, "No useful move found", one_sec());
+                return;
             }
+        };
+
+        let msg = self.describe_move(mv);
+        let prev = self.fc.clone();
+        let mut next = prev.clone();
+        next.apply_move(mv).expect("legal move failed");
+
+        if !self.confirm_fill(game, &next) {
+            return;
         }
+
+        self.push_undo();
+        self.fc = next;
+        self.hints += 1;
+        self.mark_moved(&prev, false);
+        self.hold_exposed(&prev);
+        self.try_sweep = true;
+
+        self.show_message(game, &format!("Moved {}", msg), one_sec());
     }
 
-    fn sweep_step(&mut self, game: &mut Game) {
-        if self.fc.sweep_step(3) {
-            game.redraw();
-        } else {
-            self.try_sweep = false;
+    fn describe_move(&self, mv: Move) -> String {
+        let card = match self.fc.moved_card(mv) {
+            Some(c) => self.config.card_name(c),
+            None => return String::new()
+        };
+
+        match mv {
+            Move::ReserveToFoundation(_) | Move::TableauToFoundation(_) =>
+                format!("{} to foundation", card),
+            Move::TableauToReserve(_) =>
+                format!("{} to reserve", card),
+            Move::ReserveToTableau(_, b) | Move::TableauToTableau(_, b, _) =>
+                format!("{} to column {}", card, self.config.slot_name(b)),
         }
     }
 
-    fn action_str(&self) -> String {
-        use self::Action::*;
+    /// Makes the most useful legal move of the card sought by lookup.
+    fn smart_move(&mut self, game: &mut Game) {
+        let card = match self.locate {
+            Some(Locate{suit: Some(suit), what: Match::Value(n), ..}) =>
+                Card::new(suit, Face(n)),
+            _ => {
+                self.show_message(game, "Choose a suit and value to move a card", one_sec());
+                return;
+            }
+        };
 
-        match self.action {
-            Some(Reserve) => "R".to_owned(),
-            Some(ReserveSlot(n)) => format!("R {}", SLOT_NAMES[n as usize]),
-            Some(Slot(n)) => format!("{}", SLOT_NAMES[n as usize]),
-            _ => "".to_owned(),
+        let name = self.config.card_name(card);
+
+        match self.fc.find(card) {
+            Some(Position::Foundation) => {
+                self.show_message(game, &format!("{} is on foundation", name), one_sec());
+                return;
+            }
+            Some(Position::Tableau(a, i))
+                    if self.fc.group_size(a) < self.fc.tableau(a).len() - i => {
+                let n = self.fc.tableau(a).len() - i - 1;
+                self.show_message(game,
+                    &format!("{} is buried under {} cards", name, n), one_sec());
+                return;
+            }
+            _ => ()
+        }
+
+        // Prefer foundation, then building on the tableau,
+        // then an empty column, then reserve
+        let rank = |fc: &FreeCell, mv: Move| match mv {
+            Move::ReserveToFoundation(_) | Move::TableauToFoundation(_) => 0,
+            Move::ReserveToTableau(_, b) | Move::TableauToTableau(_, b, _)
+                if !fc.tableau(b).is_empty() => 1,
+            Move::ReserveToTableau(..) | Move::TableauToTableau(..) => 2,
+            Move::TableauToReserve(_) => 3,
+        };
+
+        let mv = self.fc.legal_moves().into_iter()
+            .filter(|&mv| self.fc.moved_card(mv) == Some(card))
+            .min_by_key(|&mv| rank(&self.fc, mv));
+
+        match mv {
+            Some(mv) => {
+                let prev = self.fc.clone();
+                let mut next = prev.clone();
+                next.apply_move(mv).expect("legal move failed");
+
+                if !self.confirm_fill(game, &next) {
+                    return;
+                }
+
+                self.push_undo();
+                self.fc = next;
+                self.mark_moved(&prev, false);
+                self.hold_exposed(&prev);
+                self.try_sweep = true;
+                self.locate = None;
+            }
+            None => self.show_message(game, &format!("No legal move for {}", name), one_sec())
         }
     }
 
     fn begin_locate(&mut self) {
         self.locate = Some(Locate{
             color: None,
+            suit: None,
             what: Match::Nothing,
         });
     }
 
+    /// Returns the style added to a slot to show that it has focus.
+    fn focus_style(&self, zone: Zone, pos: usize) -> Style {
+        if self.focus == Some(Focus{zone: zone, pos: pos}) {
+            Style::REVERSE
+        } else {
+            Style::empty()
+        }
+    }
+
+    /// Moves focus to the next or previous group of slots;
+    /// past the tableau or the reserve, focus is removed.
+    fn cycle_focus_zone(&mut self, forward: bool) {
+        let zone = match (self.focus.map(|f| f.zone), forward) {
+            (None, true) | (Some(Zone::Foundation), false) => Some(Zone::Reserve),
+            (Some(Zone::Reserve), true) | (Some(Zone::Tableau), false) => Some(Zone::Foundation),
+            (Some(Zone::Foundation), true) | (None, false) => Some(Zone::Tableau),
+            (Some(Zone::Tableau), true) | (Some(Zone::Reserve), false) => None,
+        };
+
+        self.focus = zone.map(|zone| Focus{zone: zone, pos: 0});
+    }
+
+    /// Moves focus forward or back within its group of slots, wrapping around.
+    fn move_focus(&mut self, forward: bool) {
+        if let Some(ref mut focus) = self.focus {
+            let n = match focus.zone {
+                Zone::Reserve => self.fc.reserve_count(),
+                Zone::Foundation => 1,
+                Zone::Tableau => self.fc.tableau_count(),
+            };
+
+            focus.pos = if forward {
+                (focus.pos + 1) % n
+            } else {
+                (focus.pos + n - 1) % n
+            };
+        }
+    }
+
+    /// References the focused slot, as its key would.
+    fn focus_action(&mut self, game: &mut Game) {
+        match self.focus {
+            Some(Focus{zone: Zone::Reserve, pos}) => {
+                self.action(game, Action::Reserve);
+
+                // A reserve slot is referenced by R, then the slot key
+                if self.action.is_some() {
+                    self.action(game, Action::Slot(pos as u8));
+                }
+            }
+            Some(Focus{zone: Zone::Foundation, ..}) =>
+                self.action(game, Action::Foundation),
+            Some(Focus{zone: Zone::Tableau, pos}) =>
+                self.action(game, Action::Slot(pos as u8)),
+            None => ()
+        }
+    }
+
     fn clear_action(&mut self, game: &mut Game) {
         self.action = None;
         game.redraw();
@@ -692,62 +3745,571 @@ impl FreeCellGame {
         self.game_won = true;
         game.pause();
         self.pause_draw = Draw::Victory;
+
+        let t = self.play_time(game);
+        // A custom board shares its seed with a deal, but not its stats
+        let prev_best = if self.custom {
+            None
+        } else {
+            self.stats.variant_mut(self.variant).best_times.get(&self.seed).cloned()
+        };
+
+        if let Some(best) = prev_best {
+            if t < best {
+                self.show_message(game, &format!("New personal best for this deal! Previous: {}",
+                    time_str(best).trim()), Some(Duration::from_secs(3)));
+            }
+        }
+
+        self.game_end(game, EndReason::Finish);
+
+        let mut sum = self.summary(game, prev_best.map_or(false, |best| t < best));
+
+        if self.config.rate_moves && !self.custom {
+            sum.par = self.stats.variant_mut(self.variant).par_moves.get(&self.seed).cloned();
+            sum.par_pending = sum.par.is_none();
+        }
+
+        self.summary = Some(sum);
+
+        if self.config.win_animation {
+            self.celebration = Some(Celebration::new(&self.fc));
+        }
+
+        self.schedule_auto_deal();
+    }
+
+    /// Records the current game as lost and shows its summary.
+    fn game_lost(&mut self, game: &mut Game) {
+        self.game_end(game, EndReason::Resign);
+        self.summary = Some(self.summary(game, false));
+
+        game.pause();
+        self.pause_draw = Draw::Defeat;
+        self.schedule_auto_deal();
+    }
+
+    /// Arranges for a new game to be dealt once the summary has been shown
+    /// for the configured time.
+    fn schedule_auto_deal(&mut self) {
+        if self.config.auto_deal != 0 && self.tutorial.is_none() {
+            self.auto_deal_at = Some(Instant::now() +
+                Duration::from_secs(self.config.auto_deal));
+        }
+    }
+
+    /// Deals a new game if the summary has been shown for the configured time.
+    ///
+    /// The time is counted from when the summary is first shown,
+    /// after any victory animation and search for par.
+    fn check_auto_deal(&mut self, game: &mut Game) {
+        let at = match self.auto_deal_at {
+            Some(at) => at,
+            None => return
+        };
+
+        let waiting = self.celebration.is_some() ||
+            self.summary.as_ref().map_or(false, |sum| sum.par_pending);
+
+        if waiting {
+            self.schedule_auto_deal();
+        } else if Instant::now() >= at {
+            self.deal(game);
+        }
+    }
+
+    fn summary(&mut self, game: &Game, new_best: bool) -> Summary {
+        Summary{
+            time: self.play_time(game),
+            moves: self.last_move(),
+            undos: self.undos,
+            hints: self.hints,
+            seed: self.seed,
+            new_best: new_best,
+            streak: self.stats.variant_mut(self.variant).current_streak,
+            par: None,
+            par_pending: false,
+        }
+    }
+
+    /// Finds the length of a solver solution of the won deal,
+    /// keeping it in stats so that it is found only once for each deal.
+    fn find_par(&mut self, game: &mut Game) {
+        let seed = match self.summary {
+            Some(ref mut sum) if sum.par_pending => {
+                sum.par_pending = false;
+                sum.seed
+            }
+            _ => return
+        };
+
+        let fc = FreeCell::with_seed(self.variant, seed);
+
+        if let Solution::Solved(moves) = solve(&fc, PAR_BUDGET) {
+            let par = moves.len() as u32;
+
+            self.stats.variant_mut(self.variant).par_moves.insert(seed, par);
+            self.save_stats(game);
+
+            if let Some(ref mut sum) = self.summary {
+                sum.par = Some(par);
+            }
+        }
+    }
+
+    /// Returns the number of challenge deals solved in the current variant.
+    fn challenges_solved(&mut self) -> usize {
+        let best_times = &self.stats.variant_mut(self.variant).best_times;
+
+        CHALLENGE_DEALS.iter().filter(|seed| best_times.contains_key(seed)).count()
+    }
+
+    /// Returns the index of the first challenge deal, starting from
+    /// the one selected, not yet solved in the current variant.
+    fn next_unsolved_challenge(&mut self) -> Option<usize> {
+        let start = self.challenge;
+        let best_times = &self.stats.variant_mut(self.variant).best_times;
+        let n = CHALLENGE_DEALS.len();
+
+        (0..n).map(|i| (start + i) % n)
+            .find(|&i| !best_times.contains_key(&CHALLENGE_DEALS[i]))
+    }
+
+    /// Ends the current game and plays the selected challenge deal.
+    fn confirm_challenge(&mut self, game: &mut Game) {
+        let seed = CHALLENGE_DEALS[self.challenge];
+
+        if self.in_progress() &&
+                !self.confirm(game, &format!("Abandon this game to play deal {}?", seed)) {
+            return;
+        }
+
+        self.game_end(game, EndReason::Finish);
+        self.deal_seed(game, seed);
+
+        if game.paused() {
+            game.toggle_pause();
+        }
     }
 
     fn new_game(&mut self, game: &mut Game) {
-        self.game_end(game);
+        self.game_end(game, EndReason::Finish);
+        self.deal(game);
+    }
+
+    /// Ends the current game and starts a new game of the given variant.
+    pub fn set_variant(&mut self, game: &mut Game, variant: Variant) {
+        self.game_end(game, EndReason::Finish);
+        self.variant = variant;
+        self.deal(game);
+    }
+
+    fn confirm_next_variant(&mut self, game: &mut Game) {
+        let variant = self.variant.next();
+
+        if self.confirm(game, &format!("Start a new game of {}?", variant.name())) {
+            self.set_variant(game, variant);
+        }
+    }
+
+    /// Deals a new board without recording the end of the current game.
+    fn deal(&mut self, game: &mut Game) {
+        let seed = thread_rng().gen();
+        self.deal_seed(game, seed);
+    }
+
+    /// Ends the current game and starts the given board,
+    /// whose result will not be recorded in stats.
+    fn play_board(&mut self, game: &mut Game, fc: FreeCell) {
+        self.game_end(game, EndReason::Finish);
+        self.variant = fc.variant();
+        self.deal(game);
+        self.fc = fc;
+        self.custom = true;
+    }
+
+    /// Ends the current game and deals it again.
+    fn restart(&mut self, game: &mut Game) {
+        if self.custom {
+            let fc = self.undo.first().cloned().unwrap_or_else(|| self.fc.clone());
+            self.play_board(game, fc);
+            return;
+        }
+
+        let seed = self.seed;
+
+        self.game_end(game, EndReason::Finish);
+        self.deal_seed(game, seed);
+    }
+
+    fn confirm_restart(&mut self, game: &mut Game) {
+        if self.confirm(game, "Restart this deal?") {
+            self.restart(game);
+        }
+    }
+
+    /// Deals the board identified by the given seed,
+    /// without recording the end of the current game.
+    fn deal_seed(&mut self, game: &mut Game, seed: u32) {
         game.reset_time();
+        self.time_offset = 0;
+        self.resumed = false;
+        self.recorded = false;
+        self.custom = false;
+        self.undos = 0;
+        self.hints = 0;
+        self.penalty = 0;
+        self.summary = None;
+        self.auto_deal_at = None;
+        self.finishing = false;
+        self.finish_offered = false;
+        self.end_undo_group();
+        self.last_foundation = None;
+        self.locked.clear();
+        self.placed = None;
+        self.diff = None;
 
         self.action = None;
         self.locate = None;
+        self.focus = None;
+        self.analysis = None;
+        self.replay = None;
+        self.celebration = None;
+        self.clear_moved();
         self.game_won = false;
         self.undo.clear();
         self.undo_index = 0;
         self.pause_draw = Draw::Pause;
-        self.fc = FreeCell::new();
+        self.seed = seed;
+        self.fc = FreeCell::with_seed(self.variant, seed);
+
+        if let Some(config) = self.config.handicap.deal_config(self.variant) {
+            match FreeCell::with_config(self.variant, seed, &config) {
+                Ok(fc) => {
+                    // A handicapped deal is kept out of stats
+                    self.fc = fc;
+                    self.custom = true;
+                }
+                Err(e) => self.show_message(game, &format!("Handicap not applied: {}", e),
+                    Some(Duration::from_secs(3)))
+            }
+        }
+
         self.try_sweep = true;
+        self.begin_deal();
+        game.redraw();
+
+        if self.custom {
+            return;
+        }
+
+        if let Some(&best) = self.stats.variant_mut(self.variant).best_times.get(&seed) {
+            self.show_message(game, &format!("Personal best: {} \u{2014} beat it!",
+                time_str(best).trim()), Some(Duration::from_secs(3)));
+        }
+    }
+
+    fn begin_deal(&mut self) {
+        if self.config.deal_animation {
+            self.dealing = Some(0);
+        }
+    }
+
+    /// Shows the next row of dealt cards.
+    fn deal_step(&mut self, game: &mut Game) {
+        if let Some(n) = self.dealing {
+            let n = n + self.fc.tableau_count();
+            let total = self.fc.tableau_slots().iter().map(|t| t.len()).sum();
+
+            self.dealing = if n < total { Some(n) } else { None };
+            game.redraw();
+        }
+    }
+
+    /// Sets aside the current game and starts the tutorial.
+    fn begin_tutorial(&mut self, game: &mut Game) {
+        let saved = SaveFile{
+            fc: self.fc.clone(),
+            seed: Some(self.seed),
+            undo: take(&mut self.undo),
+            undo_index: self.undo_index,
+            time: self.play_time(game),
+            undos: self.undos,
+            hints: self.hints,
+            penalty: self.penalty,
+        };
+
+        game.reset_time();
+        self.time_offset = 0;
+        self.penalty = 0;
+
+        self.tutorial = Some(Tutorial{
+            step: 0,
+            saved: saved,
+        });
+        self.load_tutorial_step(game);
+    }
+
+    /// Sets up the board for the current tutorial step.
+    fn load_tutorial_step(&mut self, game: &mut Game) {
+        let step = match self.tutorial {
+            Some(ref t) => t.step,
+            None => return
+        };
+
+        self.action = None;
+        self.locate = None;
+        self.dealing = None;
+        self.undo.clear();
+        self.undo_index = 0;
+        self.fc = (STEPS[step].board)();
+        self.clear_moved();
+
+        if game.paused() {
+            game.toggle_pause();
+        }
         game.redraw();
     }
 
+    fn next_tutorial_step(&mut self, game: &mut Game) {
+        let finished = match self.tutorial {
+            Some(ref mut t) => {
+                t.step += 1;
+                t.step == STEPS.len()
+            }
+            None => return
+        };
+
+        if finished {
+            self.end_tutorial(game);
+            self.show_message(game, "Tutorial complete!", one_sec());
+        } else {
+            self.load_tutorial_step(game);
+        }
+    }
+
+    /// Advances the tutorial if the expected move was made;
+    /// otherwise, if any other move was made, restarts the step.
+    fn check_tutorial(&mut self, game: &mut Game) {
+        let step = match self.tutorial {
+            Some(ref t) => t.step,
+            None => return
+        };
+
+        if (STEPS[step].done)(&self.fc) {
+            self.show_message(game, "Well done!", one_sec());
+            self.next_tutorial_step(game);
+        } else if !self.undo.is_empty() {
+            self.show_message(game, "Not quite; try again", one_sec());
+            self.load_tutorial_step(game);
+        }
+    }
+
+    /// Leaves the tutorial, restoring the interrupted game
+    /// at the pause screen.
+    fn end_tutorial(&mut self, game: &mut Game) {
+        if let Some(t) = self.tutorial.take() {
+            self.fc = t.saved.fc;
+            self.seed = t.saved.seed.unwrap_or(self.seed);
+            self.undo = t.saved.undo;
+            self.undo_index = t.saved.undo_index;
+            self.undos = t.saved.undos;
+            self.hints = t.saved.hints;
+            self.penalty = t.saved.penalty;
+            self.action = None;
+            self.locate = None;
+            self.clear_moved();
+            self.try_sweep = true;
+
+            game.reset_time();
+            self.time_offset = t.saved.time;
+
+            game.pause();
+            self.pause_draw = Draw::Pause;
+        }
+    }
+
     fn push_undo(&mut self) {
+        if self.undo_group {
+            return;
+        }
+
         self.undo.drain(self.undo_index..);
         self.undo.push(self.fc.clone());
         self.undo_index = self.undo.len();
+        self.diff = None;
+    }
+
+    /// Begins collecting moves into one undo step, saving the board as it is.
+    ///
+    /// Until `end_undo_group` is called, `push_undo` has no effect,
+    /// so that one undo reverts every move made in between.
+    fn begin_undo_group(&mut self) {
+        self.push_undo();
+        self.undo_group = true;
+    }
+
+    /// Ends collecting moves into one undo step.
+    fn end_undo_group(&mut self) {
+        self.undo_group = false;
     }
 
+    /// Reverts the last move, along with any cards it sent to foundation,
+    /// restoring the board exactly as it was when the move was made.
     fn undo(&mut self, game: &mut Game) {
+        if let Err(msg) = self.undo_state() {
+            self.show_message(game, msg, one_sec());
+        }
+    }
+
+    /// Goes to the previous state in the undo history,
+    /// or returns the reason there is none.
+    fn undo_state(&mut self) -> Result<(), &'static str> {
+        self.clear_moved();
+
         if self.undo.is_empty() {
-            game.set_message("No changes made", one_sec());
+            return Err("No changes made");
         } else if self.undo_index == 0 {
-            game.set_message("Already at initial state", one_sec());
+            return Err("Already at initial state");
+        }
+
+        let new_fc = self.undo[self.undo_index - 1].clone();
+
+        if self.undo_index == self.undo.len() {
+            self.finish_sweep();
+            let fc = replace(&mut self.fc, new_fc);
+            self.undo.push(fc);
+        } else {
+            self.fc = new_fc;
+        }
+        self.undo_index -= 1;
+        self.count_undo();
+        self.stop_sweep();
+        self.update_diff();
+        Ok(())
+    }
+
+    /// Counts a return to an earlier state, adding any undo penalty
+    /// to play time.
+    fn count_undo(&mut self) {
+        self.undos += 1;
+
+        if self.tutorial.is_none() {
+            self.time_offset += self.config.undo_penalty;
+            self.penalty += self.config.undo_penalty;
+        }
+    }
+
+    /// Completes any pending foundation moves at once,
+    /// so that the newest state is kept in the undo history as it would end.
+    fn finish_sweep(&mut self) {
+        if self.try_sweep && self.config.autoplay == AutoplayMode::Safe {
+            let hold = self.sweep_exclusions();
+
+            while self.fc.sweep_step_except(u32::max_value(), &hold) {}
+        }
+    }
+
+    /// Cancels pending foundation moves after going to a state in the undo
+    /// history; each is the board as the player saw it and is left as it is.
+    fn stop_sweep(&mut self) {
+        self.try_sweep = false;
+        self.finishing = false;
+        self.end_undo_group();
+        self.last_foundation = None;
+        self.sweep_wait = 0;
+        self.held.clear();
+        self.placed = None;
+    }
+
+    /// Returns the number of moves leading to the newest state
+    /// in the undo history.
+    fn last_move(&self) -> usize {
+        if self.undo_index == self.undo.len() {
+            self.undo.len()
+        } else {
+            self.undo.len() - 1
+        }
+    }
+
+    /// Goes to the state after the given number of moves in the undo history.
+    fn jump_to(&mut self, game: &mut Game, n: usize) {
+        if let Err(msg) = self.jump_state(n) {
+            self.show_message(game, &msg, one_sec());
+        }
+    }
+
+    /// Goes to the state after the given number of moves in the undo history,
+    /// or returns the reason there is none.
+    fn jump_state(&mut self, n: usize) -> Result<(), String> {
+        let last = self.last_move();
+
+        self.clear_moved();
+
+        if self.undo.is_empty() {
+            return Err("No changes made".to_owned());
+        } else if n > last {
+            return Err(format!("No move {}; last move is {}", n, last));
+        } else if n == self.undo_index {
+            // Already there
+        } else if self.undo_index == self.undo.len() {
+            self.count_undo();
+
+            // Leaving the newest state, which is kept only in `fc`
+            self.finish_sweep();
+            let new_fc = self.undo[n].clone();
+            let fc = replace(&mut self.fc, new_fc);
+            self.undo.push(fc);
+            self.undo_index = n;
+            self.stop_sweep();
+        } else if n == last {
+            // Returning to the newest state, which is then kept only in `fc`
+            self.fc = self.undo.pop().unwrap();
+            self.undo_index = self.undo.len();
+            self.stop_sweep();
         } else {
-            let new_fc = self.undo[self.undo_index - 1].clone();
+            self.stop_sweep();
 
-            if self.undo_index == self.undo.len() {
-                let fc = replace(&mut self.fc, new_fc);
-                self.undo.push(fc);
-            } else {
-                self.fc = new_fc;
+            if n < self.undo_index {
+                self.count_undo();
             }
-            self.undo_index -= 1;
+            self.fc = self.undo[n].clone();
+            self.undo_index = n;
         }
+
+        self.update_diff();
+        Ok(())
     }
 
     fn redo(&mut self, game: &mut Game) {
+        match self.redo_state() {
+            Ok(()) => game.redraw(),
+            Err(msg) => self.show_message(game, msg, one_sec())
+        }
+    }
+
+    /// Goes to the next state in the undo history,
+    /// or returns the reason there is none.
+    fn redo_state(&mut self) -> Result<(), &'static str> {
+        self.clear_moved();
+
         if self.undo.is_empty() {
-            game.set_message("No changes made", one_sec());
+            return Err("No changes made");
         } else if self.undo_index == self.undo.len() {
-            game.set_message("Already at newest state", one_sec());
-        } else if self.undo_index == self.undo.len() - 2 {
+            return Err("Already at newest state");
+        }
+
+        if self.undo_index == self.undo.len() - 2 {
             self.undo_index += 1;
             self.fc = self.undo.pop().unwrap();
         } else {
             self.undo_index += 1;
             self.fc = self.undo[self.undo_index].clone();
-
-            game.redraw();
-            self.try_sweep = true;
         }
+
+        self.stop_sweep();
+        self.update_diff();
+        Ok(())
     }
 }
 
@@ -770,18 +4332,56 @@ impl GameImpl for FreeCellGame {
     }
 
     fn on_key_event(&mut self, game: &mut Game, key: Key) {
-        if self.wait_confirm {
-            match key {
-                Key::Char('y') => self.confirm_result = true,
-                _ => self.confirm_result = false
+        self.last_key = Instant::now();
+
+        if self.idle_paused {
+            self.idle_paused = false;
+
+            // Any key resumes a game paused for inactivity;
+            // the key is not otherwise handled.
+            if game.paused() && self.pause_draw == Draw::Pause {
+                game.toggle_pause();
+                game.redraw();
+                return;
             }
+        }
 
-            // Terminate this level of the main loop.
-            game.quit();
+        if self.dealing.is_some() {
+            // Any key skips the deal animation;
+            // the key is then handled as usual.
+            self.dealing = None;
+            game.redraw();
+        } else if !game.paused() && self.tutorial.is_none() {
+            // Likewise, foundation moves in progress are completed
+            self.skip_sweep(game);
+        }
+
+        if self.line_input.is_some() {
+            self.line_input_key(game, key);
         } else if game.paused() {
+            match key {
+                // Any other key ends the animation, showing the summary
+                Key::Char('n') | Key::Char('r') | Key::Char('m') => (),
+                _ if self.celebration.is_some() => {
+                    self.celebration = None;
+                    game.redraw();
+                    return;
+                }
+                _ => ()
+            }
+
+            // Any key on the summary stops a new game being dealt
+            self.auto_deal_at = None;
+
+            if self.pause_draw == Draw::Replay {
+                self.replay_key(key);
+                game.redraw();
+                return;
+            }
+
             match key {
                 Key::Escape | Key::Char(' ') | Key::Char('p')
-                        if self.pause_draw != Draw::Victory => {
+                        if !self.pause_draw.game_over() => {
                     game.toggle_pause()
                 }
                 Key::Char('c') if self.pause_draw == Draw::Stats => {
@@ -789,8 +4389,46 @@ impl GameImpl for FreeCellGame {
                         self.clear_stats(game);
                     }
                 }
-                Key::Char('n') if self.pause_draw == Draw::Victory =>
+                Key::Char('q') if self.tutorial.is_some() =>
+                    self.end_tutorial(game),
+                // Other game commands wait until the tutorial is over
+                _ if self.tutorial.is_some() => return,
+                Key::Char('t') if self.pause_draw == Draw::Pause =>
+                    self.begin_tutorial(game),
+                Key::Char('c') if self.pause_draw == Draw::Pause =>
+                    self.config.next_sweep_preset(),
+                Key::Char('a') if self.pause_draw == Draw::Pause ||
+                        self.pause_draw == Draw::Defeat =>
+                    self.begin_analysis(),
+                Key::Char('v') if self.pause_draw == Draw::Pause =>
+                    self.confirm_next_variant(game),
+                Key::Char('n') if self.pause_draw.game_over() =>
                     self.new_game(game),
+                Key::Char('r') if self.pause_draw.game_over() =>
+                    self.restart(game),
+                Key::Char('m') if self.pause_draw.game_over() =>
+                    self.pause_draw = Draw::Pause,
+                Key::Char('w') if self.pause_draw.game_over() =>
+                    self.begin_replay(),
+                Key::Char('r') if self.pause_draw == Draw::Pause =>
+                    self.confirm_restart(game),
+                Key::Char('h') if self.pause_draw == Draw::Pause =>
+                    self.pause_draw = Draw::Challenge,
+                Key::Up if self.pause_draw == Draw::Challenge =>
+                    self.challenge = self.challenge.saturating_sub(1),
+                Key::Down if self.pause_draw == Draw::Challenge =>
+                    self.challenge = min(self.challenge + 1, CHALLENGE_DEALS.len() - 1),
+                Key::Enter if self.pause_draw == Draw::Challenge =>
+                    self.confirm_challenge(game),
+                Key::Char('n') if self.pause_draw == Draw::Challenge => {
+                    match self.next_unsolved_challenge() {
+                        Some(i) => {
+                            self.challenge = i;
+                            self.confirm_challenge(game);
+                        }
+                        None => self.show_message(game, "All challenges solved!", one_sec())
+                    }
+                }
                 Key::Char('n') => self.confirm_new_game(game),
                 Key::Char('q') => self.confirm_quit(game),
                 _ => return
@@ -802,14 +4440,31 @@ impl GameImpl for FreeCellGame {
                     game.redraw();
                     return;
                 }
+                Key::Enter => {
+                    self.smart_move(game);
+                    game.redraw();
+                    return;
+                }
                 _ => ()
             }
 
             let loc = self.locate.as_mut().unwrap();
 
             match key {
-                Key::Char('b') => loc.color = Some(Color::Black),
-                Key::Char('r') => loc.color = Some(Color::Red),
+                Key::Char('b') => {
+                    loc.color = Some(Color::Black);
+                    loc.suit = None;
+                }
+                Key::Char('r') => {
+                    loc.color = Some(Color::Red);
+                    loc.suit = None;
+                }
+                Key::Char(ch @ 'c') | Key::Char(ch @ 'd') |
+                Key::Char(ch @ 'h') | Key::Char(ch @ 's') => {
+                    let suit = Suit::from_letter(ch);
+                    loc.color = suit.map(|s| s.color());
+                    loc.suit = suit;
+                }
                 Key::Char('l') => loc.what = Match::Low,
                 Key::Char('a') => loc.what = Match::Value(ACE),
                 Key::Char(n @ '2' ..= '9') => loc.what = Match::Value(n as u8 - b'0'),
@@ -822,23 +4477,55 @@ impl GameImpl for FreeCellGame {
         } else {
             if self.action.is_none() {
                 match key {
+                    Key::Char('n') if self.tutorial.is_some() =>
+                        self.next_tutorial_step(game),
+                    Key::Char('q') if self.tutorial.is_some() =>
+                        self.end_tutorial(game),
+                    Key::Char('R') if self.tutorial.is_some() => (),
                     Key::Char('l') => self.begin_locate(),
+                    Key::Char('L') => {
+                        self.begin_locate();
+                        if let Some(ref mut loc) = self.locate {
+                            loc.what = Match::Low;
+                        }
+                    }
                     Key::Char('n') => self.confirm_new_game(game),
                     Key::Char('p') => {
                         game.pause();
                         self.pause_draw = Draw::Pause;
                     }
                     Key::Char('q') => self.confirm_quit(game),
+                    Key::Char('R') => self.confirm_resign(game),
                     Key::Char('u') => self.undo(game),
+                    Key::Char('U') => self.prompt_jump(game),
+                    Key::Char('D') => self.prompt_diff(game),
                     Key::Ctrl('r') => self.redo(game),
                     Key::Char('S') => {
                         game.pause();
                         self.pause_draw = Draw::Stats;
                     }
+                    Key::Char('#') => {
+                        self.config.show_labels = !self.config.show_labels;
+                    }
+                    Key::Char('C') => {
+                        self.config.show_suit_counts = !self.config.show_suit_counts;
+                    }
+                    Key::Char('i') => {
+                        self.config.show_info = !self.config.show_info;
+                    }
+                    Key::Char('H') => self.hint(game),
+                    Key::Char('A') => self.assist(game),
+                    Key::Char('F') => self.take_back_foundation(game),
+                    Key::Char('E') => self.start_finish(game),
                     Key::Char('?') => {
                         game.pause();
                         self.pause_draw = Draw::Help;
                     }
+                    // Not listed in help; intended for testing rules
+                    Key::Ctrl('l') => {
+                        game.pause();
+                        self.pause_draw = Draw::Legality;
+                    }
                     _ => ()
                 }
             }
@@ -847,31 +4534,110 @@ impl GameImpl for FreeCellGame {
                 Key::Escape | Key::Char(' ') => self.clear_action(game),
                 Key::Char('r') => self.action(game, Action::Reserve),
                 Key::Char('t') => self.action(game, Action::Foundation),
-                Key::Char('a') => self.action(game, Action::Slot(0)),
-                Key::Char('s') => self.action(game, Action::Slot(1)),
-                Key::Char('d') => self.action(game, Action::Slot(2)),
-                Key::Char('f') => self.action(game, Action::Slot(3)),
-                Key::Char('g') => self.action(game, Action::Slot(4)),
-                Key::Char('h') => self.action(game, Action::Slot(5)),
-                Key::Char('j') => self.action(game, Action::Slot(6)),
-                Key::Char('k') => self.action(game, Action::Slot(7)),
+                Key::Char('K') => self.toggle_lock(game),
+                // mortal reports no Shift-Tab, so Up and Down cycle both ways
+                Key::Tab | Key::Down => self.cycle_focus_zone(true),
+                Key::Up => self.cycle_focus_zone(false),
+                Key::Left => self.move_focus(false),
+                Key::Right => self.move_focus(true),
+                Key::Enter => self.focus_action(game),
+                Key::Char(ch) => {
+                    let n_slots = max(self.fc.tableau_count(), self.fc.reserve_count());
+
+                    match self.config.slot_key(ch) {
+                        Some(n) if (n as usize) < n_slots =>
+                            self.action(game, Action::Slot(n)),
+                        _ => ()
+                    }
+                }
 
                 _ => ()
             }
+
+            self.check_tutorial(game);
         }
 
         game.redraw();
     }
 
     fn on_tick(&mut self, game: &mut Game) -> io::Result<()> {
+        if self.launch_pending {
+            self.launch_pending = false;
+            self.launch(game);
+        }
+
+        if let Some(msg) = self.notice.take() {
+            self.show_message(game, &msg, Some(Duration::from_secs(5)));
+            game.redraw();
+        }
+
+        self.announce_events(game);
+
+        if game.paused() && self.pause_draw == Draw::Victory {
+            if let Some(ref mut cel) = self.celebration {
+                cel.step(game.screen().size());
+                game.redraw();
+            }
+        }
+
+        // Par is found once the summary, showing that it is being found,
+        // has been drawn
+        if game.paused() && self.pause_draw == Draw::Victory && self.celebration.is_none() &&
+                self.summary.as_ref().map_or(false, |sum| sum.par_pending) {
+            self.find_par(game);
+            game.redraw();
+        }
+
+        if game.paused() && self.pause_draw.game_over() {
+            self.check_auto_deal(game);
+        }
+
+        if game.paused() && self.pause_draw == Draw::Replay {
+            if self.replay.as_mut().map_or(false, |rep| rep.tick()) {
+                game.redraw();
+            }
+        }
+
+        if game.paused() && self.pause_draw == Draw::Analysis {
+            if let Some(ref mut analysis) = self.analysis {
+                if !analysis.done() {
+                    analysis.step();
+                    game.redraw();
+                }
+            }
+        }
+
+        if !game.paused() && self.idle() {
+            game.pause();
+            self.pause_draw = Draw::Pause;
+            self.idle_paused = true;
+            game.redraw();
+        }
+
         if !game.paused() {
             // Redraw the clock
             game.draw_title(true);
             game.refresh()?;
 
-            if self.fc.game_over() {
+            if self.recent_ticks != 0 {
+                self.recent_ticks -= 1;
+
+                if self.recent_ticks == 0 {
+                    self.recent.clear();
+                    game.redraw();
+                }
+            }
+
+            if self.dealing.is_some() {
+                self.deal_step(game);
+            } else if self.tutorial.is_some() {
+                // Tutorial boards change only by the moves each step asks for
+            } else if self.fc.game_over() {
                 self.game_won(game);
-            } else if self.try_sweep {
+            } else if self.try_sweep && self.action.is_none() {
+                // Sweeping is held while a move is partially entered,
+                // so that the selected source is not changed before
+                // the destination key arrives.
                 self.sweep_step(game);
             }
         }
@@ -880,20 +4646,577 @@ impl GameImpl for FreeCellGame {
     }
 }
 
-fn draw_card(screen: &mut Screen, card: Card, highlight: bool) {
-    let sty = if highlight {
-        Style::REVERSE
-    } else {
-        Style::empty()
-    };
+/// Awaits the answer to a `y/n` confirmation, in a nested main loop.
+///
+/// The board is drawn and ticks are handled by the parent game,
+/// while keys answer the prompt.
+struct ConfirmPrompt<'a> {
+    parent: &'a mut FreeCellGame,
+    result: bool,
+    /// Time at which the confirmation was requested
+    start: Instant,
+}
+
+impl<'a> ConfirmPrompt<'a> {
+    fn new(parent: &'a mut FreeCellGame) -> ConfirmPrompt<'a> {
+        ConfirmPrompt{
+            parent,
+            result: false,
+            start: Instant::now(),
+        }
+    }
+
+    /// Returns whether the configured time has passed without an answer.
+    fn expired(&self) -> bool {
+        let timeout = self.parent.config.confirm_timeout;
+        timeout != 0 && self.start.elapsed() >= Duration::from_secs(timeout)
+    }
+}
+
+impl<'a> GameImpl for ConfirmPrompt<'a> {
+    fn draw(&mut self, game: &mut Game) {
+        self.parent.draw(game);
+    }
 
-    let fg = card.suit.color().term_color();
+    fn on_key_event(&mut self, game: &mut Game, key: Key) {
+        self.parent.last_key = Instant::now();
+        self.result = key == Key::Char('y');
+
+        // Terminate this level of the main loop.
+        game.quit();
+    }
+
+    fn on_tick(&mut self, game: &mut Game) -> io::Result<()> {
+        if self.expired() {
+            // An unanswered confirmation is declined
+            self.result = false;
+            game.quit();
+            return Ok(());
+        }
+
+        self.parent.on_tick(game)
+    }
+}
+
+/// Columns taken by a suit character; the layout of the board assumes
+/// that each is drawn one column wide
+const SUIT_WIDTH: usize = 1;
+
+/// Returns the number of columns taken by a card drawn by `draw_card`:
+/// in compact style, the suit and a one-character rank;
+/// otherwise, the suit, a space, and the rank, padded to two columns.
+fn card_width(compact: bool) -> usize {
+    if compact { SUIT_WIDTH + 1 } else { SUIT_WIDTH + 3 }
+}
+
+fn draw_card(screen: &mut Screen, card: Card, config: &Config, sty: Style, compact: bool) {
+    let fg = config.suit_color(card.suit);
     let bg = None;
-    let s = format!("{} {:>2}", card.suit.char(), card.value);
+    let suit = config.suit_char(card.suit);
+    let s = if compact {
+        match card.value.0 {
+            10 => format!("{}T", suit),
+            _ => format!("{}{}", suit, card.value)
+        }
+    } else {
+        format!("{} {:>2}", suit, card.value)
+    };
 
     screen.write_styled(fg, bg, sty, &s);
 }
 
+/// Breaks text into lines of at most `width` characters, at spaces.
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(take(&mut line));
+        }
+
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Returns each visible card, with its location.
+fn card_places(fc: &FreeCell) -> Vec<(Card, Position)> {
+    let mut places = Vec::with_capacity(56);
+
+    for (i, r) in fc.reserve_iter() {
+        if let Some(c) = r {
+            places.push((c, Position::Reserve(i)));
+        }
+    }
+
+    for (_, f) in fc.foundation_iter() {
+        if let Some(c) = f {
+            places.push((c, Position::Foundation));
+        }
+    }
+
+    for (i, t) in fc.tableau_slots().iter().enumerate() {
+        for (j, &c) in t.iter().enumerate() {
+            places.push((c, Position::Tableau(i, j)));
+        }
+    }
+
+    places
+}
+
+/// Returns a letter grade for the moves of a won game,
+/// against the length of a solver solution.
+///
+/// The solver searches best-first, so its solution is not necessarily
+/// the shortest; fewer moves than par earn an `A` as well.
+fn rating(moves: usize, par: u32) -> char {
+    match moves * 100 / max(par as usize, 1) {
+        0 ..= 110 => 'A',
+        111 ..= 125 => 'B',
+        126 ..= 150 => 'C',
+        _ => 'D'
+    }
+}
+
+/// Formats a time as `M:SS`, or `H:MM:SS` for an hour or more.
 fn time_str(secs: u32) -> String {
-    format!("{:>2}:{:02}", secs / 60, secs % 60)
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{:>2}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::parse_move;
+
+    /// Foundations at five, with moves of the top cards of columns 1 and 3
+    /// leading to a chain of foundation moves
+    const SWEEP_BOARD: &str = "quick_free_cell/--------/5C5D5H5S/\
+        6C7H/6D7S/6H7C/6S7D/8C/8D/8H/8S";
+
+    fn session() -> FreeCellGame {
+        FreeCellGame::with_data_dir(None).unwrap()
+    }
+
+    fn board(s: &str) -> FreeCell {
+        FreeCell::from_compact_string(s).unwrap()
+    }
+
+    /// Makes a move as the player would, leaving foundation moves pending.
+    fn play(fcg: &mut FreeCellGame, mv: &str) {
+        let mv = parse_move(&fcg.fc, mv).unwrap();
+
+        fcg.push_undo();
+        fcg.fc.apply_move(mv).unwrap();
+        fcg.try_sweep = true;
+    }
+
+    fn swept(fc: &FreeCell) -> String {
+        let mut fc = fc.clone();
+        while fc.sweep_step(u32::max_value()) {}
+        fc.to_compact_string()
+    }
+
+    #[test]
+    fn undo_reverts_move_and_sweeps() {
+        let mut fcg = session();
+        fcg.fc = board(SWEEP_BOARD);
+
+        play(&mut fcg, "1r");
+        let after = swept(&fcg.fc);
+
+        while fcg.fc.sweep_step(1) {}
+        assert_eq!(fcg.fc.to_compact_string(), after);
+
+        fcg.undo_state().unwrap();
+        assert_eq!(fcg.fc.to_compact_string(), SWEEP_BOARD);
+
+        fcg.redo_state().unwrap();
+        assert_eq!(fcg.fc.to_compact_string(), after);
+    }
+
+    #[test]
+    fn undo_completes_pending_sweeps() {
+        let mut fcg = session();
+        fcg.fc = board(SWEEP_BOARD);
+
+        play(&mut fcg, "1r");
+        let after = swept(&fcg.fc);
+
+        // Undone before any foundation move is made
+        fcg.undo_state().unwrap();
+        assert_eq!(fcg.fc.to_compact_string(), SWEEP_BOARD);
+
+        fcg.redo_state().unwrap();
+        assert_eq!(fcg.fc.to_compact_string(), after);
+    }
+
+    #[test]
+    fn undo_keeps_locked_cards() {
+        let mut fcg = session();
+        fcg.fc = board(SWEEP_BOARD);
+        fcg.locked.push(Card::from_code("6C").unwrap());
+
+        play(&mut fcg, "1r");
+        fcg.undo_state().unwrap();
+        fcg.redo_state().unwrap();
+
+        assert_eq!(fcg.fc.tableau(0), &[Card::from_code("6C").unwrap()]);
+        assert_eq!(fcg.fc.foundation(Suit::Club), Card::from_code("5C"));
+    }
+
+    #[test]
+    fn reserve_target_leftmost() {
+        let mut fcg = session();
+        fcg.fc = board("quick_free_cell/--7H----/5C5D5H5S/6C/6D7S/6H7C/6S7D/8C/8D/8H/8S");
+
+        assert_eq!(fcg.reserve_target(1, None), Ok(0));
+        assert_eq!(fcg.reserve_target(1, Some(3)), Ok(3));
+        assert_eq!(fcg.reserve_target(1, Some(1)), Err("Reserve slot is occupied"));
+        assert_eq!(fcg.reserve_target(1, Some(4)), Err("Invalid reserve slot"));
+
+        fcg.fc = board("quick_free_cell/--7H----/5C5D5H5S//6D7S/6H7C/6S7D/8C/8D/8H/8S6C");
+        assert_eq!(fcg.reserve_target(0, None), Err("Tableau slot is empty"));
+    }
+
+    #[test]
+    fn reserve_target_full() {
+        let mut fcg = session();
+        fcg.fc = board("quick_free_cell/7H6D7S6H/5C5D5H5S/6C/7C/6S7D/8C/8D/8H/8S/");
+
+        assert_eq!(fcg.reserve_target(0, None), Err("No free reserve slots"));
+        assert_eq!(fcg.reserve_target(0, Some(0)), Err("Reserve slot is occupied"));
+    }
+
+    #[test]
+    fn slot_keys_checked() {
+        assert!(validate_slot_keys("12345678").is_ok());
+        assert!(validate_slot_keys("asdfghjk").is_ok());
+
+        assert!(validate_slot_keys("1234567").is_err());
+        assert!(validate_slot_keys("123456789").is_err());
+        assert!(validate_slot_keys("1234567A").is_ok());
+        assert!(validate_slot_keys("1234567a").is_ok());
+        assert!(validate_slot_keys("1234566a").is_err());
+        assert!(validate_slot_keys("aA345678").is_err());
+        assert!(validate_slot_keys("1234567r").is_err());
+        assert!(validate_slot_keys("1234567 ").is_err());
+        assert!(validate_slot_keys("1234567\u{e9}").is_err());
+    }
+
+    #[test]
+    fn slot_keys_refer_to_slots() {
+        let mut fcg = session();
+        fcg.config.slot_keys = "12345678".chars().collect();
+
+        assert_eq!(fcg.config.slot_key('1'), Some(0));
+        assert_eq!(fcg.config.slot_key('8'), Some(7));
+        assert_eq!(fcg.config.slot_key('a'), None);
+        assert_eq!(fcg.config.slot_name(2), '3');
+        assert_eq!(fcg.config.slot_name(8), ' ');
+    }
+
+    fn save(fc: FreeCell, undo: Vec<FreeCell>, undo_index: usize) -> SaveFile {
+        SaveFile{
+            fc: fc,
+            seed: None,
+            undo: undo,
+            undo_index: undo_index,
+            time: 0,
+            undos: 0,
+            hints: 0,
+            penalty: 0,
+        }
+    }
+
+    fn invalid_data(res: io::Result<()>) -> bool {
+        res.map_err(|e| e.kind()) == Err(io::ErrorKind::InvalidData)
+    }
+
+    #[test]
+    fn check_save_undo_index() {
+        let fc = board(SWEEP_BOARD);
+        let states = |n| vec![fc.clone(); n];
+
+        assert!(check_save(&save(fc.clone(), states(0), 0)).is_ok());
+        assert!(check_save(&save(fc.clone(), states(2), 2)).is_ok());
+        assert!(check_save(&save(fc.clone(), states(3), 1)).is_ok());
+
+        assert!(invalid_data(check_save(&save(fc.clone(), states(0), 1))));
+        assert!(invalid_data(check_save(&save(fc.clone(), states(2), 3))));
+        // Past the last state kept before the newest
+        assert!(invalid_data(check_save(&save(fc.clone(), states(2), 1))));
+    }
+
+    #[test]
+    fn check_save_duplicate_ace() {
+        let fc = board(SWEEP_BOARD);
+        let mut dup = fc.clone();
+        dup.tableau_mut(4).push(Card::from_code("AC").unwrap());
+
+        assert!(invalid_data(check_save(&save(dup.clone(), Vec::new(), 0))));
+        assert!(invalid_data(check_save(&save(fc, vec![dup], 1))));
+    }
+
+    #[test]
+    fn undo_at_limits() {
+        let mut fcg = session();
+        fcg.fc = board(SWEEP_BOARD);
+
+        assert_eq!(fcg.undo_state(), Err("No changes made"));
+
+        play(&mut fcg, "1r");
+        fcg.undo_state().unwrap();
+
+        assert_eq!(fcg.undo_state(), Err("Already at initial state"));
+        fcg.redo_state().unwrap();
+        assert_eq!(fcg.redo_state(), Err("Already at newest state"));
+    }
+
+    #[test]
+    fn undo_reverts_finish_at_once() {
+        let start = "quick_free_cell/6C------/5C5D5H5S/\
+            8C7C/7D6D/8D/7H6H/8H/7S6S/8S/";
+        let mut fcg = session();
+        fcg.fc = board(start);
+
+        // As in `start_finish` and `finish_step`, one card at a time
+        fcg.begin_undo_group();
+        while let Some(moves) = fcg.fc.finish_moves() {
+            if moves.is_empty() {
+                break;
+            }
+            fcg.push_undo();
+            fcg.fc.apply_move(moves[0]).unwrap();
+        }
+        fcg.end_undo_group();
+        assert!(fcg.fc.game_over());
+
+        fcg.undo_state().unwrap();
+        assert_eq!(fcg.fc.to_compact_string(), start);
+        assert_eq!(fcg.undo_state(), Err("Already at initial state"));
+
+        fcg.redo_state().unwrap();
+        assert!(fcg.fc.game_over());
+    }
+
+    #[test]
+    fn sweep_excludes_locked_cards() {
+        let mut fcg = session();
+        fcg.fc = board("quick_free_cell/6D------/5C5D5H5S/\
+            8C6C7C/7D/8D/7H6H/8H/7S6S/8S/");
+        fcg.locked = vec![Card::from_code("6D").unwrap()];
+
+        assert_eq!(fcg.sweep_exclusions(), fcg.locked);
+
+        // Held cards are released once the game can be finished
+        fcg.fc = board("quick_free_cell/6D------/5C5D5H5S/\
+            8C7C/7D6C/8D/7H6H/8H/7S6S/8S/");
+        assert_eq!(fcg.sweep_exclusions(), vec![]);
+    }
+
+    #[test]
+    fn corrupt_stats_set_aside() {
+        use std::fs::{create_dir_all, read_to_string, remove_dir_all, write};
+
+        let dir = env::temp_dir().join(format!("freecell-stats-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        write(dir.join("stats.cfg"), "{\"games\": ").unwrap();
+
+        let fcg = FreeCellGame::with_data_dir(Some(dir.clone())).unwrap();
+        let backup = read_to_string(dir.join("stats.cfg.bak"));
+        remove_dir_all(&dir).unwrap();
+
+        assert_eq!(fcg.stats.games, 0);
+        assert_eq!(fcg.stats.won, 0);
+        assert!(fcg.notice.unwrap().starts_with("Stats file is corrupt"));
+        assert_eq!(backup.unwrap(), "{\"games\": ");
+    }
+
+    #[test]
+    fn cleared_tableau_layout() {
+        let mut fcg = session();
+        fcg.fc = board("quick_free_cell/--------/8C8D8H8S////////");
+
+        let card_w = card_width(false);
+        let col_w = card_w + 2;
+        let header = fcg.tableau_header(col_w, card_w);
+
+        assert_eq!(fcg.tableau_rows(), 0);
+        assert_eq!(header.len(), 8 * col_w - 2);
+        assert_eq!(header.chars().nth(1 + 7 * col_w), Some(fcg.config.slot_name(7)));
+    }
+
+    /// Plays three moves from `SWEEP_BOARD`, none of which lead to foundation,
+    /// returning the board after each number of moves.
+    fn three_moves(fcg: &mut FreeCellGame) -> Vec<String> {
+        fcg.fc = board(SWEEP_BOARD);
+        let mut states = vec![SWEEP_BOARD.to_owned()];
+
+        for mv in &["5r", "6r", "7r"] {
+            play(fcg, mv);
+            states.push(fcg.fc.to_compact_string());
+        }
+
+        states
+    }
+
+    #[test]
+    fn jump_from_newest_and_back() {
+        let mut fcg = session();
+        let states = three_moves(&mut fcg);
+
+        fcg.jump_state(1).unwrap();
+        assert_eq!(fcg.fc.to_compact_string(), states[1]);
+        assert_eq!((fcg.undo_index, fcg.undo.len()), (1, 4));
+
+        fcg.jump_state(3).unwrap();
+        assert_eq!(fcg.fc.to_compact_string(), states[3]);
+        assert_eq!((fcg.undo_index, fcg.undo.len()), (3, 3));
+        assert_eq!(fcg.redo_state(), Err("Already at newest state"));
+    }
+
+    #[test]
+    fn jump_within_history() {
+        let mut fcg = session();
+        let states = three_moves(&mut fcg);
+
+        fcg.jump_state(0).unwrap();
+        assert_eq!(fcg.fc.to_compact_string(), states[0]);
+
+        fcg.jump_state(2).unwrap();
+        assert_eq!(fcg.fc.to_compact_string(), states[2]);
+        assert_eq!((fcg.undo_index, fcg.undo.len()), (2, 4));
+
+        fcg.redo_state().unwrap();
+        assert_eq!(fcg.fc.to_compact_string(), states[3]);
+        assert_eq!((fcg.undo_index, fcg.undo.len()), (3, 3));
+    }
+
+    #[test]
+    fn jump_out_of_range() {
+        let mut fcg = session();
+        assert_eq!(fcg.jump_state(0), Err("No changes made".to_owned()));
+
+        let states = three_moves(&mut fcg);
+
+        assert_eq!(fcg.jump_state(4), Err("No move 4; last move is 3".to_owned()));
+        fcg.jump_state(3).unwrap();
+        assert_eq!(fcg.fc.to_compact_string(), states[3]);
+
+        fcg.jump_state(1).unwrap();
+        assert_eq!(fcg.jump_state(4), Err("No move 4; last move is 3".to_owned()));
+        assert_eq!(fcg.fc.to_compact_string(), states[1]);
+    }
+
+    #[test]
+    fn focus_zones_cycle_both_ways() {
+        let mut fcg = session();
+        let zones = |fcg: &mut FreeCellGame, forward| (0..4).map(|_| {
+            fcg.cycle_focus_zone(forward);
+            fcg.focus.map(|f| f.zone)
+        }).collect::<Vec<_>>();
+
+        assert_eq!(zones(&mut fcg, true),
+            [Some(Zone::Reserve), Some(Zone::Foundation), Some(Zone::Tableau), None]);
+        assert_eq!(zones(&mut fcg, false),
+            [Some(Zone::Tableau), Some(Zone::Foundation), Some(Zone::Reserve), None]);
+    }
+
+    #[test]
+    fn streak_freeze_kept_without_streak() {
+        let mut stats = Stats::default();
+        stats.streak_freezes = 1;
+
+        stats.update_streak(false, 3);
+        assert_eq!((stats.current_streak, stats.streak_freezes), (0, 1));
+
+        stats.update_streak(true, 3);
+        stats.update_streak(false, 3);
+        assert_eq!((stats.current_streak, stats.streak_freezes), (1, 0));
+
+        stats.update_streak(false, 3);
+        assert_eq!((stats.current_streak, stats.streak_freezes), (0, 0));
+    }
+
+    #[test]
+    fn streak_freeze_earned() {
+        let mut stats = Stats::default();
+
+        for _ in 0..3 {
+            stats.update_streak(true, 3);
+        }
+        assert_eq!((stats.current_streak, stats.streak_freezes), (3, 1));
+    }
+
+    #[test]
+    fn finish_offered_once() {
+        let finishable = "quick_free_cell/6C------/5C5D5H5S/\
+            8C7C/7D6D/8D/7H6H/8H/7S6S/8S/";
+        let blocked = "quick_free_cell/--------/5C5D5H5S/\
+            8C6C7C/7D6D/8D/7H6H/8H/7S6S/8S/";
+        let mut fcg = session();
+
+        fcg.fc = board(blocked);
+        assert!(!fcg.take_finish_offer());
+
+        fcg.fc = board(finishable);
+        assert!(fcg.take_finish_offer());
+        // Not offered again after other moves
+        assert!(!fcg.take_finish_offer());
+
+        // Until other moves are again needed
+        fcg.fc = board(blocked);
+        assert!(!fcg.take_finish_offer());
+        fcg.fc = board(finishable);
+        assert!(fcg.take_finish_offer());
+    }
+
+    #[test]
+    fn abandoned_only_when_implicit() {
+        let mut fcg = session();
+        fcg.config.min_played_moves = 5;
+        three_moves(&mut fcg);
+
+        assert!(fcg.abandoned(EndReason::Finish));
+        assert!(!fcg.abandoned(EndReason::Resign));
+
+        fcg.config.min_played_moves = 3;
+        assert!(!fcg.abandoned(EndReason::Finish));
+    }
+
+    #[test]
+    fn tutorial_double_tap_moves() {
+        let mut fcg = session();
+        fcg.config.double_tap = DoubleTap::Off;
+        assert_eq!(fcg.double_tap(), DoubleTap::Off);
+
+        fcg.tutorial = Some(Tutorial{step: 2, saved: save(board(SWEEP_BOARD), Vec::new(), 0)});
+        assert_eq!(fcg.double_tap(), DoubleTap::Move);
+    }
+
+    #[test]
+    fn clear_stats_keeps_par() {
+        let mut stats = Stats::default();
+        stats.games = 3;
+        stats.best_times.insert(7, 100);
+        stats.par_moves.insert(7, 90);
+
+        stats.clear();
+        assert_eq!(stats.games, 0);
+        assert!(stats.best_times.is_empty());
+        assert_eq!(stats.par_moves.get(&7), Some(&90));
+    }
 }
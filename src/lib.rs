@@ -2,13 +2,146 @@
 
 pub mod freecell;
 pub mod freecell_game;
+pub mod notation;
+pub mod server;
+pub mod solver;
+mod tutorial;
 
 pub fn run() {
+    run_with_data_dir(freecell_game::default_data_dir());
+}
+
+/// Runs a game which keeps config, stats, and saved games in the given
+/// directory, rather than the user config directory; or, if `None`,
+/// keeps them only for the session.
+pub fn run_with_data_dir(data_dir: Option<std::path::PathBuf>) {
+    use freecell_game::FreeCellGame;
+    use term_game::Game;
+
+    let mut game = Game::new("FreeCell").expect("failed to initialize console");
+    let mut fc = FreeCellGame::with_data_dir(data_dir)
+        .expect("failed to initialize game");
+
+    game.run(&mut fc).unwrap();
+}
+
+/// Runs a game, as `run_with_data_dir`, which starts from the given board.
+///
+/// See `FreeCellGame::with_board` for the formats accepted.
+pub fn run_with_board(data_dir: Option<std::path::PathBuf>, board: &str) {
     use freecell_game::FreeCellGame;
     use term_game::Game;
 
     let mut game = Game::new("FreeCell").expect("failed to initialize console");
-    let mut fc = FreeCellGame::new().expect("failed to initialize game");
+    let mut fc = FreeCellGame::with_board(data_dir, board)
+        .expect("failed to initialize game");
 
     game.run(&mut fc).unwrap();
 }
+
+/// Runs a game controlled by JSON commands on standard input.
+pub fn run_server() {
+    server::run().expect("failed to run server");
+}
+
+/// Writes stats kept in the given data directory to the given path as CSV,
+/// reporting any error on standard error.
+pub fn run_export_stats(data_dir: Option<&std::path::Path>, path: &str) {
+    if let Err(e) = freecell_game::export_stats(data_dir, path.as_ref()) {
+        eprintln!("failed to export stats to {}: {}", path, e);
+        std::process::exit(1);
+    }
+}
+
+/// Error returned by `verify_game` for an illegal move,
+/// or by `verify_replay` for a move whose board does not match its hash
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct VerifyError {
+    /// Index of the failed move
+    pub index: usize,
+    /// Reason the move failed
+    pub error: VerifyFailure,
+}
+
+/// Reason a move of a recorded game failed verification
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum VerifyFailure {
+    /// The move is illegal
+    Illegal(freecell::MoveError),
+    /// The board after the move does not match the recorded hash
+    HashMismatch,
+}
+
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.error {
+            VerifyFailure::Illegal(e) => write!(f, "move {}: {}", self.index + 1, e),
+            VerifyFailure::HashMismatch =>
+                write!(f, "move {}: board does not match recorded hash", self.index + 1),
+        }
+    }
+}
+
+/// Plays the given moves on the deal of the given variant and seed,
+/// returning whether they win the game.
+///
+/// As in interactive play, cards are automatically moved to foundation
+/// before the first move and after each move, so recorded games and
+/// solver solutions need not include those moves.
+pub fn verify_game(variant: freecell::Variant, seed: u32, moves: &[freecell::Move])
+        -> Result<bool, VerifyError> {
+    replay(variant, seed, moves, |_, _| true)
+}
+
+/// Plays the given moves as `verify_game` does, also checking that the board
+/// after each move, and any foundation moves which follow, has the recorded
+/// `canonical_hash`.
+///
+/// A move without a recorded hash is not checked against one.
+/// The hashes detect corrupted or altered records; they are not proof
+/// against a deliberately forged record.
+pub fn verify_replay(variant: freecell::Variant, seed: u32,
+        steps: &[(freecell::Move, Option<u64>)]) -> Result<bool, VerifyError> {
+    let moves = steps.iter().map(|&(mv, _)| mv).collect::<Vec<_>>();
+
+    replay(variant, seed, &moves, |i, fc| {
+        steps[i].1.map_or(true, |h| h == fc.canonical_hash())
+    })
+}
+
+/// Returns the `canonical_hash` of the board after each of the given moves,
+/// played as `verify_game` does, to be recorded alongside them.
+pub fn replay_hashes(variant: freecell::Variant, seed: u32, moves: &[freecell::Move])
+        -> Result<Vec<u64>, VerifyError> {
+    let mut hashes = Vec::with_capacity(moves.len());
+
+    replay(variant, seed, moves, |_, fc| {
+        hashes.push(fc.canonical_hash());
+        true
+    })?;
+
+    Ok(hashes)
+}
+
+/// Plays the given moves, with automatic foundation moves, calling `check`
+/// with the index of each move and the board which results.
+fn replay<F>(variant: freecell::Variant, seed: u32, moves: &[freecell::Move], mut check: F)
+        -> Result<bool, VerifyError>
+        where F: FnMut(usize, &freecell::FreeCell) -> bool {
+    let mut fc = freecell::FreeCell::with_seed(variant, seed);
+
+    while fc.sweep_step(u32::max_value()) {}
+
+    for (i, &mv) in moves.iter().enumerate() {
+        fc.apply_move(mv)
+            .map_err(|e| VerifyError{index: i, error: VerifyFailure::Illegal(e)})?;
+
+        while fc.sweep_step(u32::max_value()) {}
+
+        if !check(i, &fc) {
+            return Err(VerifyError{index: i, error: VerifyFailure::HashMismatch});
+        }
+    }
+
+    Ok(fc.game_over())
+}
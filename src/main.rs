@@ -1,5 +1,57 @@
 extern crate freecell;
 
+use std::io::Read;
+use std::path::PathBuf;
+
+fn usage() -> ! {
+    eprintln!("usage: freecell [--data-dir <dir>] [--server | --export-stats <path> | --board <board>]");
+    std::process::exit(1);
+}
+
+/// What to run, as chosen by command line options
+enum Mode {
+    Play,
+    Server,
+    ExportStats(String),
+    Board(String),
+}
+
 pub fn main() {
-    freecell::run();
+    let mut args = std::env::args().skip(1);
+    let mut data_dir = None;
+    let mut mode = None;
+
+    // Options may be given in any order, but each only once
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--data-dir" if data_dir.is_none() =>
+                data_dir = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--server" if mode.is_none() => mode = Some(Mode::Server),
+            "--export-stats" if mode.is_none() =>
+                mode = Some(Mode::ExportStats(args.next().unwrap_or_else(|| usage()))),
+            "--board" if mode.is_none() =>
+                mode = Some(Mode::Board(args.next().unwrap_or_else(|| usage()))),
+            _ => usage()
+        }
+    }
+
+    let data_dir = data_dir.or_else(freecell::freecell_game::default_data_dir);
+
+    match mode.unwrap_or(Mode::Play) {
+        Mode::Play => freecell::run_with_data_dir(data_dir),
+        Mode::Server => freecell::run_server(),
+        Mode::ExportStats(path) => freecell::run_export_stats(data_dir.as_deref(), &path),
+        Mode::Board(mut board) => {
+            if board == "-" {
+                board.clear();
+
+                if let Err(e) = std::io::stdin().read_to_string(&mut board) {
+                    eprintln!("failed to read board: {}", e);
+                    std::process::exit(1);
+                }
+            }
+
+            freecell::run_with_board(data_dir, &board)
+        }
+    }
 }
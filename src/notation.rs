@@ -0,0 +1,242 @@
+//! Move notation
+//!
+//! A move is written as a source slot followed by a destination slot.
+//! Tableau columns are numbered from `1`; reserve slots are lettered from `a`,
+//! skipping `h`, which stands for the foundation. For example, `2h` moves
+//! the top card of column 2 to foundation and `3a` moves it to reserve slot `a`.
+//!
+//! A move between tableau columns carries as many cards as may legally be
+//! moved, unless a count follows a colon: `46:2` moves two cards from
+//! column 4 to column 6.
+//!
+//! As a destination, `r` stands for the first free reserve slot.
+//! The `Display` form of a `Move`, which is written without reference to
+//! a board, uses it for moves to reserve and always gives a count for moves
+//! between tableau columns.
+//!
+//! A step of a recorded game may follow its move with `@` and the
+//! `canonical_hash` of the board after the move, in hexadecimal:
+//! `2h@3f0c5e9a1b2d4c6e`. The hashes let a verifier check its replay
+//! against the recording; they guard against corruption, not forgery.
+
+use std::fmt;
+
+use crate::freecell::{FreeCell, Move, MoveError};
+
+/// Reserve slot letters, in slot order
+const RESERVE_NAMES: &[u8] = b"abcdefgijk";
+
+/// Letter standing for the foundation
+const FOUNDATION_NAME: char = 'h';
+
+/// Letter standing for the first free reserve slot
+const FREE_RESERVE_NAME: char = 'r';
+
+/// Reason a move could not be parsed
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ParseMoveError {
+    /// The move is not well-formed
+    Syntax,
+    /// A slot named in the move does not exist
+    NoSuchSlot,
+    /// The destination reserve slot is not the first vacant slot,
+    /// where the card would be placed
+    ReserveSlot,
+    /// The move is not legal
+    Move(MoveError),
+    /// The board hash following the move is not well-formed
+    Hash,
+}
+
+impl fmt::Display for ParseMoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseMoveError::Syntax => f.write_str("Invalid move notation"),
+            ParseMoveError::NoSuchSlot => f.write_str("No such slot"),
+            ParseMoveError::ReserveSlot =>
+                f.write_str("Cards move to the first free reserve slot"),
+            ParseMoveError::Move(e) => fmt::Display::fmt(&e, f),
+            ParseMoveError::Hash => f.write_str("Invalid board hash"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Slot {
+    Reserve(usize),
+    FreeReserve,
+    Foundation,
+    Tableau(usize),
+}
+
+fn parse_slot(fc: &FreeCell, ch: char) -> Result<Slot, ParseMoveError> {
+    if ch == FOUNDATION_NAME {
+        Ok(Slot::Foundation)
+    } else if ch == FREE_RESERVE_NAME {
+        Ok(Slot::FreeReserve)
+    } else if let Some(d) = ch.to_digit(10) {
+        let pos = (d as usize).checked_sub(1).ok_or(ParseMoveError::NoSuchSlot)?;
+
+        if pos < fc.tableau_count() {
+            Ok(Slot::Tableau(pos))
+        } else {
+            Err(ParseMoveError::NoSuchSlot)
+        }
+    } else if ch.is_ascii_lowercase() {
+        match RESERVE_NAMES.iter().position(|&c| c as char == ch) {
+            Some(pos) if pos < fc.reserve_count() => Ok(Slot::Reserve(pos)),
+            _ => Err(ParseMoveError::NoSuchSlot)
+        }
+    } else {
+        Err(ParseMoveError::Syntax)
+    }
+}
+
+fn reserve_name(pos: usize) -> char {
+    RESERVE_NAMES[pos] as char
+}
+
+fn tableau_name(pos: usize) -> String {
+    (pos + 1).to_string()
+}
+
+/// Parses a move in notation, as it would be made on the given board.
+///
+/// The move is not checked for legality, except to determine
+/// the number of cards moved between tableau columns.
+pub fn parse_move(fc: &FreeCell, s: &str) -> Result<Move, ParseMoveError> {
+    let s = s.trim();
+
+    let (slots, count) = match s.find(':') {
+        Some(pos) => {
+            let n = s[pos + 1..].parse::<usize>()
+                .map_err(|_| ParseMoveError::Syntax)?;
+            (&s[..pos], Some(n))
+        }
+        None => (s, None)
+    };
+
+    let mut chars = slots.chars();
+
+    let (src, dst) = match (chars.next(), chars.next(), chars.next()) {
+        (Some(a), Some(b), None) => (parse_slot(fc, a)?, parse_slot(fc, b)?),
+        _ => return Err(ParseMoveError::Syntax)
+    };
+
+    if count.is_some() {
+        match (src, dst) {
+            (Slot::Tableau(_), Slot::Tableau(_)) => (),
+            _ => return Err(ParseMoveError::Syntax)
+        }
+    }
+
+    match (src, dst) {
+        (Slot::Reserve(a), Slot::Foundation) => Ok(Move::ReserveToFoundation(a)),
+        (Slot::Reserve(a), Slot::Tableau(b)) => Ok(Move::ReserveToTableau(a, b)),
+        (Slot::Tableau(a), Slot::Foundation) => Ok(Move::TableauToFoundation(a)),
+        (Slot::Tableau(a), Slot::Reserve(b)) => {
+            if fc.free_reserve_slot() == Some(b) {
+                Ok(Move::TableauToReserve(a))
+            } else if fc.reserve_free() {
+                Err(ParseMoveError::ReserveSlot)
+            } else {
+                Err(ParseMoveError::Move(MoveError::ReserveFull))
+            }
+        }
+        (Slot::Tableau(a), Slot::FreeReserve) => {
+            if fc.reserve_free() {
+                Ok(Move::TableauToReserve(a))
+            } else {
+                Err(ParseMoveError::Move(MoveError::ReserveFull))
+            }
+        }
+        (Slot::Tableau(a), Slot::Tableau(b)) => {
+            let n = match count {
+                Some(n) => n,
+                None => fc.tableau_move_size(a, b).map_err(ParseMoveError::Move)?
+            };
+            Ok(Move::TableauToTableau(a, b, n))
+        }
+        _ => Err(ParseMoveError::Move(MoveError::InvalidDestination))
+    }
+}
+
+/// Writes a move in notation, as it would be made on the given board.
+pub fn format_move(fc: &FreeCell, mv: Move) -> String {
+    match mv {
+        Move::ReserveToFoundation(a) =>
+            format!("{}{}", reserve_name(a), FOUNDATION_NAME),
+        Move::ReserveToTableau(a, b) =>
+            format!("{}{}", reserve_name(a), tableau_name(b)),
+        Move::TableauToFoundation(a) =>
+            format!("{}{}", tableau_name(a), FOUNDATION_NAME),
+        Move::TableauToReserve(a) => {
+            let b = fc.free_reserve_slot().unwrap_or(0);
+            format!("{}{}", tableau_name(a), reserve_name(b))
+        }
+        Move::TableauToTableau(a, b, n) => {
+            if fc.tableau_move_size(a, b) == Ok(n) {
+                format!("{}{}", tableau_name(a), tableau_name(b))
+            } else {
+                format!("{}{}:{}", tableau_name(a), tableau_name(b), n)
+            }
+        }
+    }
+}
+
+/// Writes a step of a recorded game: a move in notation, as it would be
+/// made on the given board, and the hash of the board which results.
+pub fn format_step(fc: &FreeCell, mv: Move, hash: u64) -> String {
+    format!("{}@{:016x}", format_move(fc, mv), hash)
+}
+
+/// Parses a step of a recorded game, as it would be made on the given board,
+/// returning the move and the recorded board hash, if any.
+pub fn parse_step(fc: &FreeCell, s: &str) -> Result<(Move, Option<u64>), ParseMoveError> {
+    let mut parts = s.trim().splitn(2, '@');
+    let mv = parse_move(fc, parts.next().unwrap_or(""))?;
+
+    let hash = match parts.next() {
+        Some(h) => Some(u64::from_str_radix(h, 16).map_err(|_| ParseMoveError::Hash)?),
+        None => None
+    };
+
+    Ok((mv, hash))
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Move::ReserveToFoundation(a) =>
+                write!(f, "{}{}", reserve_name(a), FOUNDATION_NAME),
+            Move::ReserveToTableau(a, b) =>
+                write!(f, "{}{}", reserve_name(a), tableau_name(b)),
+            Move::TableauToFoundation(a) =>
+                write!(f, "{}{}", tableau_name(a), FOUNDATION_NAME),
+            Move::TableauToReserve(a) =>
+                write!(f, "{}{}", tableau_name(a), FREE_RESERVE_NAME),
+            Move::TableauToTableau(a, b, n) =>
+                write!(f, "{}{}:{}", tableau_name(a), tableau_name(b), n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn move_display() {
+        assert_eq!(Move::ReserveToFoundation(0).to_string(), "ah");
+        assert_eq!(Move::ReserveToTableau(2, 3).to_string(), "c4");
+        assert_eq!(Move::TableauToFoundation(7).to_string(), "8h");
+        assert_eq!(Move::TableauToReserve(0).to_string(), "1r");
+        assert_eq!(Move::TableauToTableau(3, 5, 2).to_string(), "46:2");
+    }
+
+    #[test]
+    fn move_display_skips_foundation_letter() {
+        // Reserve slots beyond the seventh skip `h`
+        assert_eq!(Move::ReserveToTableau(7, 0).to_string(), "i1");
+    }
+}
@@ -0,0 +1,246 @@
+//! Headless control of a game through JSON on standard input and output
+//!
+//! Each line of input is a JSON object holding one command:
+//!
+//! * `{"move": "2h"}` makes a move, written as in `notation`
+//! * `{"undo": true}` reverts the last move
+//! * `{"new": "free_cell"}` deals a new game of the given variant
+//! * `{"state": true}` makes no change
+//!
+//! Each command is answered by one line of output: a JSON object holding
+//! `ok`, whether the command succeeded; `error`, describing a failure;
+//! `board`, the current board, with cards written as codes such as `"7H"`;
+//! `legal_moves`, in notation; `won`, whether the game is won; and `events`,
+//! the notable events of a move, such as `{"column_emptied": 2}`,
+//! `{"suit_complete": "Heart"}`, or `"reserve_filled"`.
+//!
+//! After each move, cards are automatically moved to foundation,
+//! as in interactive play.
+
+use std::io::{self, BufRead, Write};
+use std::mem::replace;
+
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+
+use crate::freecell::{Event, FreeCell, Variant};
+use crate::notation::{format_move, parse_move};
+
+#[derive(Deserialize)]
+struct Command {
+    #[serde(rename = "move")]
+    mv: Option<String>,
+    undo: Option<bool>,
+    new: Option<Variant>,
+    state: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct Response {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    board: Board,
+    legal_moves: Vec<String>,
+    won: bool,
+    events: Vec<Event>,
+}
+
+#[derive(Serialize)]
+struct Board {
+    variant: Variant,
+    reserve: Vec<Option<String>>,
+    foundation: Vec<Option<String>>,
+    tableau: Vec<Vec<String>>,
+}
+
+impl Board {
+    fn new(fc: &FreeCell) -> Board {
+        Board{
+            variant: fc.variant(),
+            reserve: fc.reserve_slots().iter()
+                .map(|r| r.map(|c| c.code())).collect(),
+            foundation: fc.foundation_slots().iter()
+                .map(|f| f.map(|c| c.code())).collect(),
+            tableau: fc.tableau_slots().iter()
+                .map(|t| t.iter().map(|c| c.code()).collect()).collect(),
+        }
+    }
+}
+
+struct Server {
+    fc: FreeCell,
+    undo: Vec<FreeCell>,
+}
+
+impl Server {
+    fn new() -> Server {
+        let mut fc = FreeCell::new();
+        sweep(&mut fc);
+
+        Server{
+            fc: fc,
+            undo: Vec::new(),
+        }
+    }
+
+    fn handle(&mut self, line: &str) -> Response {
+        let prev = self.fc.clone();
+        let result = json::from_str::<Command>(line)
+            .map_err(|e| format!("Invalid command: {}", e))
+            .and_then(|cmd| self.command(cmd));
+        let events = match result {
+            Ok(true) => self.fc.events_since(&prev),
+            _ => Vec::new()
+        };
+
+        Response{
+            ok: result.is_ok(),
+            error: result.err(),
+            board: Board::new(&self.fc),
+            legal_moves: self.fc.legal_moves().into_iter()
+                .map(|mv| format_move(&self.fc, mv)).collect(),
+            won: self.fc.game_over(),
+            events: events,
+        }
+    }
+
+    /// Carries out a command, returning whether it made a move.
+    fn command(&mut self, cmd: Command) -> Result<bool, String> {
+        match cmd {
+            Command{mv: Some(s), undo: None, new: None, state: None} => {
+                let mv = parse_move(&self.fc, &s).map_err(|e| e.to_string())?;
+                let mut fc = self.fc.clone();
+
+                fc.apply_move(mv).map_err(|e| e.to_string())?;
+                sweep(&mut fc);
+
+                self.undo.push(replace(&mut self.fc, fc));
+                Ok(true)
+            }
+            Command{mv: None, undo: Some(true), new: None, state: None} => {
+                match self.undo.pop() {
+                    Some(fc) => {
+                        self.fc = fc;
+                        Ok(false)
+                    }
+                    None => Err("No moves to undo".to_owned())
+                }
+            }
+            Command{mv: None, undo: None, new: Some(variant), state: None} => {
+                self.fc = FreeCell::with_variant(variant);
+                self.undo.clear();
+                sweep(&mut self.fc);
+                Ok(false)
+            }
+            Command{mv: None, undo: None, new: None, state: Some(true)} => Ok(false),
+            _ => Err("Expected exactly one command".to_owned())
+        }
+    }
+}
+
+fn sweep(fc: &mut FreeCell) {
+    while fc.sweep_step(u32::max_value()) {}
+}
+
+/// Reads commands from standard input until it is closed,
+/// writing a response to each to standard output.
+pub fn run() -> io::Result<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut server = Server::new();
+
+    for line in stdin.lock().lines() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let resp = server.handle(&line);
+        let data = json::to_string(&resp)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+
+        writeln!(out, "{}", data)?;
+        out.flush()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Foundations at five, with no card ready to go to foundation
+    const BOARD: &str = "quick_free_cell/--------/5C5D5H5S/\
+        6C7H/6D7S/6H7C/6S7D/8C/8D/8H/8S";
+
+    fn server() -> Server {
+        Server{
+            fc: FreeCell::from_compact_string(BOARD).unwrap(),
+            undo: Vec::new(),
+        }
+    }
+
+    /// Asserts that a command failed, with an error starting with `msg`,
+    /// and left the board unchanged.
+    fn assert_failed(server: &Server, resp: &Response, msg: &str) {
+        assert!(!resp.ok);
+        assert!(resp.error.as_ref().unwrap().starts_with(msg), "{:?}", resp.error);
+        assert!(resp.events.is_empty());
+        assert_eq!(server.fc.to_compact_string(), BOARD);
+    }
+
+    #[test]
+    fn invalid_json() {
+        let mut server = server();
+        let resp = server.handle("{\"move\": ");
+
+        assert_failed(&server, &resp, "Invalid command");
+    }
+
+    #[test]
+    fn two_commands() {
+        let mut server = server();
+        let resp = server.handle("{\"move\": \"5r\", \"undo\": true}");
+
+        assert_failed(&server, &resp, "Expected exactly one command");
+    }
+
+    #[test]
+    fn illegal_move() {
+        let mut server = server();
+        let resp = server.handle("{\"move\": \"12\"}");
+
+        assert_failed(&server, &resp, "");
+        assert!(server.undo.is_empty());
+    }
+
+    #[test]
+    fn undo_without_moves() {
+        let mut server = server();
+        let resp = server.handle("{\"undo\": true}");
+
+        assert_failed(&server, &resp, "No moves to undo");
+    }
+
+    #[test]
+    fn legal_move() {
+        let mut server = server();
+        let resp = server.handle("{\"move\": \"5r\"}");
+
+        assert!(resp.ok);
+        assert_eq!(resp.error, None);
+        assert_eq!(resp.events, [Event::ColumnEmptied(4)]);
+        assert_eq!(resp.board.reserve[0], Some("8C".to_owned()));
+        assert!(resp.board.tableau[4].is_empty());
+        assert!(!resp.won);
+
+        let resp = server.handle("{\"undo\": true}");
+
+        assert!(resp.ok);
+        assert_eq!(server.fc.to_compact_string(), BOARD);
+    }
+}
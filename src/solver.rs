@@ -0,0 +1,332 @@
+//! FreeCell solver
+
+use std::cmp::{max, Ordering};
+use std::collections::{BinaryHeap, HashSet};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::thread;
+
+use crate::freecell::{Card, FreeCell, Move, Variant};
+
+/// Result of a solver search
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Solution {
+    /// Moves which win the game, not including automatic foundation moves
+    Solved(Vec<Move>),
+    /// No sequence of moves wins the game
+    Unsolvable,
+    /// The search budget was exhausted before a result was found
+    Unknown,
+}
+
+impl Solution {
+    /// Returns whether the game can be won, if known.
+    pub fn is_solvable(&self) -> Option<bool> {
+        match *self {
+            Solution::Solved(_) => Some(true),
+            Solution::Unsolvable => Some(false),
+            Solution::Unknown => None,
+        }
+    }
+}
+
+/// Searches for a sequence of moves which wins the game.
+///
+/// After each move, cards are automatically moved to foundation,
+/// as in interactive play. At most `budget` states are expanded.
+///
+/// States are expanded best-first, ranked by an estimate of
+/// the work remaining to win the game.
+pub fn solve(fc: &FreeCell, budget: usize) -> Solution {
+    let mut fc = fc.clone();
+    sweep(&mut fc);
+
+    // Parent node and the move leading from it, for each node
+    let mut nodes: Vec<(usize, Option<Move>)> = vec![(0, None)];
+    let mut seen = HashSet::new();
+    let mut queue = BinaryHeap::new();
+
+    seen.insert(state_key(&fc));
+    queue.push(Entry{score: score(&fc, 0), depth: 0, node: 0, fc: fc});
+
+    let mut left = budget;
+    // Boards no longer needed, whose allocations are reused for new states
+    let mut pool = Vec::new();
+
+    while let Some(Entry{depth, node, fc, ..}) = queue.pop() {
+        if fc.game_over() {
+            let mut path = Vec::with_capacity(depth);
+            let mut n = node;
+
+            while let (parent, Some(mv)) = nodes[n] {
+                path.push(mv);
+                n = parent;
+            }
+
+            path.reverse();
+            return Solution::Solved(path);
+        }
+
+        if left == 0 {
+            return Solution::Unknown;
+        }
+        left -= 1;
+
+        for mv in candidate_moves(&fc) {
+            let mut next = match pool.pop() {
+                Some(mut b) => {
+                    fc.clone_into(&mut b);
+                    b
+                }
+                None => fc.clone()
+            };
+
+            next.apply_move(mv).expect("solver made an illegal move");
+            sweep(&mut next);
+
+            if seen.insert(state_key(&next)) {
+                nodes.push((node, Some(mv)));
+
+                queue.push(Entry{
+                    score: score(&next, depth + 1),
+                    depth: depth + 1,
+                    node: nodes.len() - 1,
+                    fc: next,
+                });
+            } else {
+                pool.push(next);
+            }
+        }
+
+        pool.push(fc);
+    }
+
+    Solution::Unsolvable
+}
+
+/// State awaiting expansion
+struct Entry {
+    score: usize,
+    depth: usize,
+    node: usize,
+    fc: FreeCell,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Entry) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Entry) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Entry) -> Ordering {
+        // Lowest score first; then earliest found
+        other.score.cmp(&self.score)
+            .then_with(|| other.node.cmp(&self.node))
+    }
+}
+
+/// Returns an estimate of the work remaining to win the game,
+/// plus the number of moves made so far; lower is better.
+fn score(fc: &FreeCell, depth: usize) -> usize {
+    let mut score = depth;
+
+    for t in fc.tableau_slots() {
+        if t.is_empty() {
+            continue;
+        }
+
+        for (i, &c) in t.iter().enumerate() {
+            score += 8;
+
+            // Cards buried beneath higher cards must be dug out
+            // before they can be moved to foundation.
+            if t[i + 1..].iter().any(|&above| above.value >= c.value) {
+                score += 4;
+            }
+        }
+    }
+
+    for r in fc.reserve_slots() {
+        if r.is_some() {
+            score += 10;
+        }
+    }
+
+    score
+}
+
+/// Automatically moves to foundation all cards which should be moved.
+fn sweep(fc: &mut FreeCell) {
+    while fc.sweep_step(u32::max_value()) {}
+}
+
+/// Returns legal moves worth searching.
+fn candidate_moves(fc: &FreeCell) -> Vec<Move> {
+    let first_empty = fc.empty_columns().next();
+
+    fc.legal_moves().into_iter()
+        .filter(|&mv| match mv {
+            // Empty slots are interchangeable; consider only one
+            Move::ReserveToTableau(_, b) =>
+                !fc.tableau(b).is_empty() || Some(b) == first_empty,
+            // Moving a whole column to an empty slot changes nothing
+            Move::TableauToTableau(a, b, n) if fc.tableau(b).is_empty() =>
+                Some(b) == first_empty && n != fc.tableau(a).len(),
+            _ => true
+        })
+        .collect()
+}
+
+/// Returns a key identifying a state, disregarding the order of
+/// reserve slots and of tableau slots.
+fn state_key(fc: &FreeCell) -> Vec<u8> {
+    let mut key = Vec::with_capacity(80);
+
+    for f in fc.foundation_slots() {
+        key.push(f.map_or(0, |c| c.value.0));
+    }
+
+    let mut reserve = fc.reserve_slots().iter()
+        .filter_map(|r| r.map(card_byte)).collect::<Vec<_>>();
+    reserve.sort();
+
+    key.extend(reserve);
+    key.push(0);
+
+    let mut tableau = fc.tableau_slots().iter()
+        .map(|t| t.iter().cloned().map(card_byte).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    tableau.sort();
+
+    for t in tableau {
+        key.extend(t);
+        key.push(0);
+    }
+
+    key
+}
+
+fn card_byte(card: Card) -> u8 {
+    (card.suit.as_index() as u8) << 4 | card.value.0
+}
+
+/// Solves each deal of the given variant in a range of seeds,
+/// dividing the deals among the given number of threads.
+///
+/// Results are given in seed order. Each deal is searched as by `solve`,
+/// with the same budget.
+pub fn solve_range(variant: Variant, seeds: RangeInclusive<u32>,
+        budget: usize, threads: usize) -> Vec<(u32, Solution)> {
+    // Seeds are handed out in turn; counted in 64 bits, so that going past
+    // the end of the range cannot wrap around to its start
+    let next = Arc::new(AtomicU64::new(*seeds.start() as u64));
+    let last = *seeds.end() as u64;
+
+    let workers = (0..max(threads, 1)).map(|_| {
+        let next = next.clone();
+
+        thread::spawn(move || {
+            let mut results = Vec::new();
+            let next_seed = || Some(next.fetch_add(1, AtomicOrdering::Relaxed))
+                .filter(|&seed| seed <= last).map(|seed| seed as u32);
+
+            while let Some(seed) = next_seed() {
+                results.push((seed, solve(&FreeCell::with_seed(variant, seed), budget)));
+            }
+
+            results
+        })
+    }).collect::<Vec<_>>();
+
+    let mut results = workers.into_iter()
+        .flat_map(|w| w.join().expect("solver thread panicked"))
+        .collect::<Vec<_>>();
+
+    results.sort_by_key(|&(seed, _)| seed);
+    results
+}
+
+/// Counts of the results of solving a range of deals
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct RangeSummary {
+    /// Deals for which a solution was found
+    pub solved: usize,
+    /// Deals shown to be unwinnable
+    pub unsolvable: usize,
+    /// Deals whose search ran out of budget
+    pub unknown: usize,
+}
+
+impl RangeSummary {
+    /// Counts the results given by `solve_range`.
+    pub fn new(results: &[(u32, Solution)]) -> RangeSummary {
+        let mut sum = RangeSummary::default();
+
+        for (_, solution) in results {
+            match solution.is_solvable() {
+                Some(true) => sum.solved += 1,
+                Some(false) => sum.unsolvable += 1,
+                None => sum.unknown += 1,
+            }
+        }
+
+        sum
+    }
+
+    /// Returns the percentage of deals found winnable among those
+    /// whose result is known, or `None` if there are none.
+    pub fn win_rate(&self) -> Option<f64> {
+        match self.solved + self.unsolvable {
+            0 => None,
+            known => Some(self.solved as f64 * 100.0 / known as f64)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solve_range_in_order() {
+        let results = solve_range(Variant::FreeCell, 1..=5, 100, 3);
+        let seeds = results.iter().map(|&(seed, _)| seed).collect::<Vec<_>>();
+
+        assert_eq!(seeds, [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn solve_range_at_end_of_seeds() {
+        let max = u32::max_value();
+        let results = solve_range(Variant::FreeCell, max - 1..=max, 100, 4);
+        let seeds = results.iter().map(|&(seed, _)| seed).collect::<Vec<_>>();
+
+        assert_eq!(seeds, [max - 1, max]);
+    }
+
+    #[test]
+    fn range_summary_win_rate() {
+        let results = [
+            (1, Solution::Solved(Vec::new())),
+            (2, Solution::Solved(Vec::new())),
+            (3, Solution::Unsolvable),
+            (4, Solution::Solved(Vec::new())),
+            (5, Solution::Unknown),
+        ];
+        let sum = RangeSummary::new(&results);
+
+        assert_eq!(sum, RangeSummary{solved: 3, unsolvable: 1, unknown: 1});
+        assert_eq!(sum.win_rate(), Some(75.0));
+        assert_eq!(RangeSummary::new(&results[4..]).win_rate(), None);
+    }
+}
@@ -0,0 +1,160 @@
+//! Scripted lessons introducing the rules of FreeCell
+
+use crate::freecell::{Card, FreeCell, Suit, Variant, FOUNDATION_SLOTS};
+
+/// A single lesson, posing a board and a move to be made on it
+pub struct Step {
+    /// Instructions shown beneath the board, in which a digit in braces,
+    /// such as `{0}`, stands for the key of that slot
+    pub text: &'static str,
+    /// Builds the board on which the step is played
+    pub board: fn() -> FreeCell,
+    /// Returns whether the expected move has been made
+    pub done: fn(&FreeCell) -> bool,
+}
+
+pub const STEPS: &[Step] = &[
+    Step{
+        text: "Cards are moved to the foundation, building up by suit from ace \
+            to king. Move the ace of hearts home: press {5}, then T.",
+        board: || layout(&[], &[], &[
+            (0, &[card("9S"), card("4D")]),
+            (5, &[card("8C"), card("AH")]),
+        ]),
+        done: |fc| fc.foundation(Suit::Heart).is_some(),
+    },
+    Step{
+        text: "On the tableau, cards are stacked downward in alternating colors. \
+            Move the 6 of clubs onto the 7 of hearts: press {0}, then {1}.",
+        board: || layout(&[], &[], &[
+            (0, &[card("KD"), card("6C")]),
+            (1, &[card("QS"), card("7H")]),
+        ]),
+        done: |fc| fc.tableau(1).last() == Some(&card("6C")),
+    },
+    Step{
+        text: "Each reserve slot holds any single card. Move the jack of spades \
+            to the reserve: press {2} twice.",
+        board: || layout(&[], &[], &[
+            (2, &[card("2H"), card("JS")]),
+        ]),
+        done: |fc| fc.reserve_slots().contains(&Some(card("JS"))),
+    },
+    Step{
+        text: "Cards in reserve may be played onto the tableau. Move the 5 of \
+            diamonds onto the 6 of spades: press R, then {0}, then {3}.",
+        board: || layout(&[card("5D")], &[], &[
+            (3, &[card("6S")]),
+        ]),
+        done: |fc| fc.tableau(3).last() == Some(&card("5D")),
+    },
+    Step{
+        text: "Free reserve slots and empty columns let ordered runs move together. \
+            Move the run in column {0} onto the 9 of clubs: press {0}, then {1}.",
+        board: || layout(&[], &[], &[
+            (0, &[card("KS"), card("8H"),
+                card("7S"), card("6D")]),
+            (1, &[card("9C")]),
+        ]),
+        done: |fc| fc.tableau(1).len() == 4,
+    },
+    Step{
+        text: "Continue building the foundation in order. Move the 2 of hearts \
+            home: press {4}, then T.",
+        board: || layout(&[], &[card("AH")], &[
+            (4, &[card("3C"), card("2H")]),
+        ]),
+        done: |fc| fc.foundation(Suit::Heart) == Some(card("2H")),
+    },
+];
+
+impl Step {
+    /// Returns the instructions of the step, naming each slot
+    /// by the key given for it.
+    pub fn instructions<F: Fn(usize) -> char>(&self, key: F) -> String {
+        let mut out = String::new();
+        let mut rest = self.text;
+
+        while let Some(start) = rest.find('{') {
+            let slot = rest[start + 1..].find('}')
+                .and_then(|end| rest[start + 1..start + 1 + end].parse::<usize>().ok()
+                    .map(|n| (n, start + end + 2)));
+
+            match slot {
+                Some((n, next)) => {
+                    out.push_str(&rest[..start]);
+                    out.push(key(n));
+                    rest = &rest[next..];
+                }
+                None => {
+                    out.push_str(&rest[..=start]);
+                    rest = &rest[start + 1..];
+                }
+            }
+        }
+
+        out.push_str(rest);
+        out
+    }
+}
+
+fn card(code: &str) -> Card {
+    Card::from_code(code).expect("invalid card code")
+}
+
+/// Builds a standard FreeCell board from reserve cards, foundation tops,
+/// and the cards of each listed tableau column.
+fn layout(reserve: &[Card], foundation: &[Card], tableau: &[(usize, &[Card])]) -> FreeCell {
+    let variant = Variant::FreeCell;
+
+    let mut res = vec![None; variant.reserve_slots()];
+
+    for (r, &card) in res.iter_mut().zip(reserve) {
+        *r = Some(card);
+    }
+
+    let mut found = [None; FOUNDATION_SLOTS];
+
+    for &card in foundation {
+        found[card.suit.as_index()] = Some(card);
+    }
+
+    let mut tab = vec![Vec::new(); variant.tableau_slots()];
+
+    for &(pos, cards) in tableau {
+        tab[pos] = cards.to_vec();
+    }
+
+    FreeCell::from_layout(variant, res, found, tab)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instructions_name_keys() {
+        let digits = |n: usize| (b'1' + n as u8) as char;
+
+        assert_eq!(STEPS[0].instructions(digits).rsplit(": ").next(),
+            Some("press 6, then T."));
+        assert_eq!(STEPS[3].instructions(digits).rsplit(": ").next(),
+            Some("press R, then 1, then 4."));
+
+        for step in STEPS {
+            let text = step.instructions(digits);
+            assert!(!text.contains('{') && !text.contains('}'), "{}", text);
+        }
+    }
+
+    #[test]
+    fn instructions_keep_other_braces() {
+        let step = Step{
+            text: "{x} {0}{",
+            board: || layout(&[], &[], &[]),
+            done: |_| true,
+        };
+
+        assert_eq!(step.instructions(|_| 'A'), "{x} A{");
+    }
+}
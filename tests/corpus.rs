@@ -0,0 +1,53 @@
+//! Replays each solved board in `corpus.txt`, checking that its recorded
+//! moves are still legal and still win the game.
+
+extern crate freecell;
+
+use freecell::freecell::FreeCell;
+use freecell::notation::parse_move;
+
+const CORPUS: &str = include_str!("corpus.txt");
+
+fn sweep(fc: &mut FreeCell) {
+    while fc.sweep_step(u32::max_value()) {}
+}
+
+fn check(line: &str) -> Result<(), String> {
+    let mut words = line.split_whitespace();
+
+    let board = words.next().ok_or("missing board")?;
+    let mut fc = FreeCell::from_compact_string(board).map_err(|e| e.to_string())?;
+
+    sweep(&mut fc);
+
+    for (i, s) in words.enumerate() {
+        let mv = parse_move(&fc, s)
+            .map_err(|e| format!("move {} ({}): {}", i + 1, s, e))?;
+        fc.apply_move(mv)
+            .map_err(|e| format!("move {} ({}): {}", i + 1, s, e))?;
+
+        sweep(&mut fc);
+    }
+
+    if fc.game_over() {
+        Ok(())
+    } else {
+        Err("moves do not win the game".to_owned())
+    }
+}
+
+#[test]
+fn corpus_wins() {
+    let lines = CORPUS.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect::<Vec<_>>();
+
+    let failed = lines.iter().enumerate()
+        .filter_map(|(i, line)| check(line).err()
+            .map(|e| format!("board {}: {}", i + 1, e)))
+        .collect::<Vec<_>>();
+
+    assert!(!lines.is_empty(), "corpus is empty");
+    assert!(failed.is_empty(), "{}", failed.join("\n"));
+}